@@ -0,0 +1,102 @@
+use std::{fmt, io};
+
+/// Everything that can go wrong while loading or saving a model or a dataset
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The data is neither a model of the current format nor a legacy one
+    BadMagic,
+    /// The model was saved by a newer version of the format
+    UnsupportedVersion(u16),
+    /// The input size of a layer doesn't match the output size of the previous one
+    ShapeMismatch {
+        layer: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The data ends before the named value
+    Truncated(&'static str),
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    UnknownActivation(String),
+    UnknownLayerType(u8),
+    /// The model declares no layer, so it can't compute anything
+    NoLayers,
+    /// Data remains after the last layer
+    TrailingData(usize),
+    /// A JSON model can't be parsed or lacks a value
+    InvalidJson(String),
+    /// A NumPy archive can't be read or lacks an array
+    InvalidNumpy(String),
+    /// An ONNX model can't be read or uses an unsupported operator
+    InvalidOnnx(String),
+    /// The model can't be written in the requested format
+    NotExportable(String),
+    UnknownNormalization(String),
+    /// The normalization is made for another number of inputs than the first layer
+    NormalizationMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// An IDX dataset file is malformed, truncated or doesn't match the other one
+    InvalidIdx(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::BadMagic => write!(f, "not a model file"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported version of the model format: {}", version)
+            }
+            Error::ShapeMismatch {
+                layer,
+                expected,
+                found,
+            } => write!(
+                f,
+                "layer {} takes {} inputs but the previous layer has {} outputs",
+                layer, found, expected
+            ),
+            Error::Truncated(what) => write!(f, "the data ends before the {}", what),
+            Error::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch (expected {:08x}, found {:08x}), the data is corrupted",
+                expected, found
+            ),
+            Error::UnknownActivation(name) => write!(f, "unknown activation: {}", name),
+            Error::UnknownLayerType(layer_type) => write!(f, "unknown layer type: {}", layer_type),
+            Error::NoLayers => write!(f, "the model has no layers"),
+            Error::TrailingData(count) => write!(f, "{} unexpected bytes after the layers", count),
+            Error::InvalidJson(message) => write!(f, "invalid JSON model: {}", message),
+            Error::InvalidNumpy(message) => write!(f, "invalid NumPy archive: {}", message),
+            Error::InvalidOnnx(message) => write!(f, "invalid ONNX model: {}", message),
+            Error::NotExportable(message) => write!(f, "can't export the model: {}", message),
+            Error::UnknownNormalization(name) => write!(f, "unknown normalization: {}", name),
+            Error::NormalizationMismatch { expected, found } => write!(
+                f,
+                "the normalization is made for {} inputs but the first layer takes {}",
+                found, expected
+            ),
+            Error::InvalidIdx(message) => write!(f, "invalid IDX file: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,323 @@
+//! IDX files, the format of the MNIST dataset.
+//!
+//! The magic number holds two zero bytes, the type of the elements and the number of dimensions.
+//! The size of each dimension follows (u32), then the elements, every value being big-endian.
+//! The first dimension counts the items of the file: images, labels...
+
+use std::io::{ErrorKind, Read};
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ElementType {
+    U8,
+    I8,
+    I16,
+    I32,
+    F32,
+    F64,
+}
+
+impl ElementType {
+    fn from_code(code: u8) -> Option<ElementType> {
+        match code {
+            0x08 => Some(ElementType::U8),
+            0x09 => Some(ElementType::I8),
+            0x0B => Some(ElementType::I16),
+            0x0C => Some(ElementType::I32),
+            0x0D => Some(ElementType::F32),
+            0x0E => Some(ElementType::F64),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            ElementType::U8 => 0x08,
+            ElementType::I8 => 0x09,
+            ElementType::I16 => 0x0B,
+            ElementType::I32 => 0x0C,
+            ElementType::F32 => 0x0D,
+            ElementType::F64 => 0x0E,
+        }
+    }
+
+    /// Number of bytes of an element
+    pub fn size(&self) -> usize {
+        match self {
+            ElementType::U8 | ElementType::I8 => 1,
+            ElementType::I16 => 2,
+            ElementType::I32 | ElementType::F32 => 4,
+            ElementType::F64 => 8,
+        }
+    }
+
+    fn value(&self, bytes: &[u8]) -> f64 {
+        match self {
+            ElementType::U8 => bytes[0] as f64,
+            ElementType::I8 => bytes[0] as i8 as f64,
+            ElementType::I16 => i16::from_be_bytes(bytes.try_into().unwrap()) as f64,
+            ElementType::I32 => i32::from_be_bytes(bytes.try_into().unwrap()) as f64,
+            ElementType::F32 => f32::from_be_bytes(bytes.try_into().unwrap()) as f64,
+            ElementType::F64 => f64::from_be_bytes(bytes.try_into().unwrap()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub element_type: ElementType,
+    /// Size of each dimension, the first one being the number of items
+    pub dims: Vec<usize>,
+}
+
+impl Header {
+    pub fn read(reader: &mut impl Read) -> Result<Header, Error> {
+        let magic = read_u32(reader, "magic number")?;
+        let [zero, zero_bis, code, dim_count] = magic.to_be_bytes();
+        if zero != 0 || zero_bis != 0 {
+            return Err(Error::InvalidIdx(format!(
+                "invalid magic number {:#010x}, not an IDX file",
+                magic
+            )));
+        }
+        let element_type = ElementType::from_code(code)
+            .ok_or_else(|| Error::InvalidIdx(format!("unknown element type {:#04x}", code)))?;
+        if dim_count == 0 {
+            return Err(Error::InvalidIdx("the file has no dimension".to_string()));
+        }
+
+        let dims = (0..dim_count)
+            .map(|_| Ok(read_u32(reader, "dimension sizes")? as usize))
+            .collect::<Result<Vec<usize>, Error>>()?;
+        let header = Header { element_type, dims };
+        header
+            .dims
+            .iter()
+            .try_fold(header.element_type.size(), |size, &dim| {
+                size.checked_mul(dim)
+            })
+            .ok_or_else(|| Error::InvalidIdx("the dimensions are too large".to_string()))?;
+
+        Ok(header)
+    }
+
+    pub fn magic_number(&self) -> u32 {
+        ((self.element_type.code() as u32) << 8) | self.dims.len() as u32
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.dims[0]
+    }
+
+    /// Number of elements of an item, the product of the sizes of all but the first dimension
+    pub fn item_size(&self) -> usize {
+        self.dims[1..].iter().product()
+    }
+}
+
+/// Reads the items of an IDX file one after the other
+pub struct Reader<R> {
+    reader: R,
+    header: Header,
+    /// Number of items already read
+    position: usize,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(mut reader: R) -> Result<Reader<R>, Error> {
+        Ok(Reader {
+            header: Header::read(&mut reader)?,
+            reader,
+            position: 0,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The elements of the next item, converted to `f64`
+    pub fn read_item(&mut self) -> Result<Vec<f64>, Error> {
+        let element_size = self.header.element_type.size();
+        let mut bytes = vec![0; self.header.item_size() * element_size];
+
+        self.reader.read_exact(&mut bytes).map_err(|error| {
+            if error.kind() == ErrorKind::UnexpectedEof {
+                Error::InvalidIdx(format!(
+                    "the data ends before the item {} of {}",
+                    self.position + 1,
+                    self.header.item_count()
+                ))
+            } else {
+                Error::Io(error)
+            }
+        })?;
+        self.position += 1;
+
+        Ok(bytes
+            .chunks_exact(element_size)
+            .map(|element| self.header.element_type.value(element))
+            .collect())
+    }
+
+    /// Check that nothing follows the last item, which also checks the end of a compressed file
+    pub fn finish(&mut self) -> Result<(), Error> {
+        let mut rest = Vec::new();
+        self.reader.read_to_end(&mut rest)?;
+        if !rest.is_empty() {
+            return Err(Error::InvalidIdx(format!(
+                "{} unexpected bytes after the last item",
+                rest.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn read_u32(reader: &mut impl Read, what: &str) -> Result<u32, Error> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes).map_err(|error| {
+        if error.kind() == ErrorKind::UnexpectedEof {
+            Error::InvalidIdx(format!("the data ends before the {}", what))
+        } else {
+            Error::Io(error)
+        }
+    })?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IDX file of elements of the type `code`, with the dimensions and the raw elements
+    fn idx(code: u8, dims: &[u32], elements: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0, 0, code, dims.len() as u8];
+        for dim in dims {
+            bytes.extend(dim.to_be_bytes());
+        }
+        bytes.extend(elements);
+        bytes
+    }
+
+    /// Message of the error given by the reader, `Error` having no `PartialEq`
+    fn error(result: Result<impl std::fmt::Debug, Error>) -> String {
+        match result {
+            Err(Error::InvalidIdx(message)) => message,
+            other => panic!("expected an InvalidIdx error, got {:?}", other),
+        }
+    }
+
+    /// Items of a whole file, checking its end
+    fn read_all(bytes: &[u8]) -> Result<(Header, Vec<Vec<f64>>), Error> {
+        let mut reader = Reader::new(bytes)?;
+        let items = (0..reader.header().item_count())
+            .map(|_| reader.read_item())
+            .collect::<Result<Vec<Vec<f64>>, Error>>()?;
+        reader.finish()?;
+        Ok((reader.header().clone(), items))
+    }
+
+    #[test]
+    fn reads_every_element_type() {
+        let cases: [(ElementType, Vec<u8>, [f64; 2]); 6] = [
+            (ElementType::U8, vec![0x00, 0xFF], [0.0, 255.0]),
+            (ElementType::I8, vec![0x7F, 0x80], [127.0, -128.0]),
+            (
+                ElementType::I16,
+                vec![0x01, 0x02, 0xFF, 0xFE],
+                [258.0, -2.0],
+            ),
+            (
+                ElementType::I32,
+                [100_000_i32.to_be_bytes(), (-7_i32).to_be_bytes()].concat(),
+                [100_000.0, -7.0],
+            ),
+            (
+                ElementType::F32,
+                [1.5_f32.to_be_bytes(), (-0.25_f32).to_be_bytes()].concat(),
+                [1.5, -0.25],
+            ),
+            (
+                ElementType::F64,
+                [0.1_f64.to_be_bytes(), 1e300_f64.to_be_bytes()].concat(),
+                [0.1, 1e300],
+            ),
+        ];
+        for (element_type, elements, expected) in cases {
+            let bytes = idx(element_type.code(), &[2, 1], &elements);
+            let (header, items) = read_all(&bytes).unwrap();
+
+            assert_eq!(header.element_type, element_type);
+            assert_eq!(header.dims, vec![2, 1]);
+            assert_eq!(
+                header.magic_number(),
+                ((element_type.code() as u32) << 8) | 2
+            );
+            assert_eq!(items, vec![vec![expected[0]], vec![expected[1]]]);
+        }
+    }
+
+    #[test]
+    fn items_hold_every_dimension_but_the_first() {
+        let bytes = idx(0x08, &[2, 2, 3], &(0..12).collect::<Vec<u8>>());
+        let (header, items) = read_all(&bytes).unwrap();
+
+        assert_eq!((header.item_count(), header.item_size()), (2, 6));
+        assert_eq!(items[1], vec![6.0, 7.0, 8.0, 9.0, 10.0, 11.0]);
+    }
+
+    #[test]
+    fn rejects_a_bad_magic_number() {
+        let mut bytes = idx(0x08, &[1], &[0]);
+        bytes[1] = 0x01;
+        assert_eq!(
+            error(read_all(&bytes)),
+            "invalid magic number 0x00010801, not an IDX file"
+        );
+
+        let bytes = idx(0x0A, &[1], &[0]);
+        assert_eq!(error(read_all(&bytes)), "unknown element type 0x0a");
+
+        let bytes = idx(0x08, &[], &[]);
+        assert_eq!(error(read_all(&bytes)), "the file has no dimension");
+    }
+
+    #[test]
+    fn rejects_a_truncated_header() {
+        assert_eq!(
+            error(read_all(&[0, 0, 0x08])),
+            "the data ends before the magic number"
+        );
+
+        // 3 dimensions announced, only 2 sizes given
+        let mut bytes = idx(0x08, &[1, 28], &[]);
+        bytes[3] = 3;
+        assert_eq!(
+            error(read_all(&bytes)),
+            "the data ends before the dimension sizes"
+        );
+
+        let bytes = idx(0x08, &[u32::MAX, u32::MAX, u32::MAX], &[]);
+        assert_eq!(error(read_all(&bytes)), "the dimensions are too large");
+    }
+
+    #[test]
+    fn rejects_a_truncated_body() {
+        let bytes = idx(0x0C, &[3, 2], &[0; 4 * 2 * 2 + 3]);
+        assert_eq!(
+            error(read_all(&bytes)),
+            "the data ends before the item 3 of 3"
+        );
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let bytes = idx(0x08, &[2, 2], &[0; 5]);
+        assert_eq!(
+            error(read_all(&bytes)),
+            "1 unexpected bytes after the last item"
+        );
+    }
+}
@@ -0,0 +1,372 @@
+//! Minimal JSON values, parser and writer.
+//!
+//! Numbers are `f64` written with the shortest representation parsing back to the same value,
+//! except the non-negative integers written without fraction nor exponent, which are kept as
+//! exact `u64`. Like Python's `json` module, `NaN`, `Infinity` and `-Infinity` are accepted and
+//! written for the non-finite numbers.
+
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    /// Integer written with digits only, exact even beyond the integers of an `f64`
+    Integer(u64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in their order of appearance
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Member of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            Value::Integer(integer) => Some(*integer as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Write the value on a single line
+    pub fn to_compact(&self) -> String {
+        let mut output = String::new();
+        self.write(&mut output, None, 0);
+        output
+    }
+
+    /// Write the value with one member or element per line, the arrays of numbers, strings...
+    /// being kept on a single line
+    pub fn to_pretty(&self) -> String {
+        let mut output = String::new();
+        self.write(&mut output, Some(2), 0);
+        output.push('\n');
+        output
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Value::Array(_) | Value::Object(_))
+    }
+
+    fn write(&self, output: &mut String, indent: Option<usize>, depth: usize) {
+        match self {
+            Value::Null => output.push_str("null"),
+            Value::Bool(value) => output.push_str(if *value { "true" } else { "false" }),
+            Value::Number(number) => write_number(output, *number),
+            Value::Integer(integer) => write!(output, "{}", integer).unwrap(),
+            Value::String(string) => output.push_str(&escape(string)),
+            Value::Array(values) => {
+                let inline = indent.is_none() || values.iter().all(Value::is_scalar);
+                output.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+                    match indent {
+                        Some(_) if inline && index > 0 => output.push(' '),
+                        Some(indent) if !inline => new_line(output, indent, depth + 1),
+                        _ => {}
+                    }
+                    value.write(output, indent, depth + 1);
+                }
+                if let (Some(indent), false) = (indent, inline || values.is_empty()) {
+                    new_line(output, indent, depth);
+                }
+                output.push(']');
+            }
+            Value::Object(members) => {
+                output.push('{');
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+                    if let Some(indent) = indent {
+                        new_line(output, indent, depth + 1);
+                    }
+                    output.push_str(&escape(name));
+                    output.push(':');
+                    if indent.is_some() {
+                        output.push(' ');
+                    }
+                    value.write(output, indent, depth + 1);
+                }
+                if let (Some(indent), false) = (indent, members.is_empty()) {
+                    new_line(output, indent, depth);
+                }
+                output.push('}');
+            }
+        }
+    }
+}
+
+fn new_line(output: &mut String, indent: usize, depth: usize) {
+    output.push('\n');
+    output.extend(std::iter::repeat_n(' ', indent * depth));
+}
+
+fn write_number(output: &mut String, number: f64) {
+    if number.is_nan() {
+        output.push_str("NaN");
+    } else if number.is_infinite() {
+        output.push_str(if number > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        });
+    } else {
+        // shortest representation reading back to the same number
+        write!(output, "{}", number).unwrap();
+    }
+}
+
+/// The string between quotes, with the characters JSON forbids escaped
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for character in value.chars() {
+        match character {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            '\t' => escaped += "\\t",
+            c if (c as u32) < 0x20 => escaped += &format!("\\u{:04x}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Parse a whole JSON document, the error describing the problem and its byte offset
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, position: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+        return Err(parser.error("unexpected data after the value"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at offset {}", message, self.position)
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    /// Consume `token` if the text continues with it
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", token)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        match self.rest().chars().next() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ if self.eat("NaN") => Ok(Value::Number(f64::NAN)),
+            _ if self.eat("Infinity") => Ok(Value::Number(f64::INFINITY)),
+            _ if self.eat("-Infinity") => Ok(Value::Number(f64::NEG_INFINITY)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of the document")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if !self.rest().starts_with('"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+            self.expect(":")?;
+            members.push((name, self.value()?));
+
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(Value::Object(members));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect("[")?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(Value::Array(values));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let length = self
+            .rest()
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(self.rest().len());
+        let token = &self.rest()[..length];
+
+        if token.bytes().all(|byte| byte.is_ascii_digit()) {
+            if let Ok(integer) = token.parse() {
+                self.position += length;
+                return Ok(Value::Integer(integer));
+            }
+        }
+        let number = token
+            .parse()
+            .map_err(|_| self.error(&format!("invalid number '{}'", token)))?;
+        self.position += length;
+        Ok(Value::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut string = String::new();
+
+        loop {
+            let character = self.next_char()?;
+
+            match character {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = self.next_char()?;
+                    match escaped {
+                        '"' | '\\' | '/' => string.push(escaped),
+                        'b' => string.push('\u{8}'),
+                        'f' => string.push('\u{c}'),
+                        'n' => string.push('\n'),
+                        'r' => string.push('\r'),
+                        't' => string.push('\t'),
+                        'u' => string.push(self.unicode_escape()?),
+                        _ => return Err(self.error("invalid escape sequence")),
+                    }
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("control character in a string")),
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Consume the next character of a string
+    fn next_char(&mut self) -> Result<char, String> {
+        let character = self
+            .rest()
+            .chars()
+            .next()
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.position += character.len_utf8();
+        Ok(character)
+    }
+
+    /// The character of a `\uXXXX` escape (the `\u` being consumed), with its low surrogate
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex_code()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+
+        if !self.eat("\\u") {
+            return Err(self.error("missing low surrogate"));
+        }
+        let low = self.hex_code()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("invalid low surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex_code(&mut self) -> Result<u32, String> {
+        let digits = self
+            .rest()
+            .get(..4)
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        let code = u32::from_str_radix(digits, 16).unwrap();
+        self.position += 4;
+        Ok(code)
+    }
+}
@@ -1,13 +1,31 @@
 use std::env;
-use std::fs::File;
-use std::io::{stdin, stdout, Write};
+use std::fs::{self, File};
+use std::io::stdin;
+use std::path::Path;
+use std::thread;
 
-use crate::network::activations;
+use crate::matrix::matrix::Matrix;
+use crate::network::activations::Activation;
+use crate::network::losses::{self, Loss};
+use crate::network::model_onnx;
 use crate::network::network::Network;
+use crate::network::normalization::{self, Normalization};
 use crate::network::training_data::TrainingData;
+use crate::network::{optimizers, schedules};
+use crate::vision::{annotation, batch, preprocessing, recognition};
 
+mod error;
+mod idx;
+mod json;
 mod matrix;
 mod network;
+mod numpy;
+mod protobuf;
+mod vision;
+
+/// Samples of each training batch, under the 32 samples needed to split a batch between threads
+/// so a seeded training gives the same model on any machine
+const BATCH_SIZE: usize = 5;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -15,22 +33,195 @@ fn main() {
     // cargo run image_path
     if args.len() == 2 {
         // run the ai on the selected path
-        println!("Oppening the image...")
+        let mut network = load_model();
+
+        println!("Oppening the image...");
+        let side = (network.input_size() as f64).sqrt() as u32;
+        let input = load_image(&args[1], side, side);
+
+        let prediction = network.recognize(&input);
+
+        println!("Predicted digit: {}", prediction.class);
+        for (class, probability) in prediction.probabilities.iter().enumerate() {
+            println!(
+                "  {}: {:.2}% (logit: {:.3})",
+                class,
+                probability * 100.0,
+                prediction.logits[class]
+            );
+        }
+    } else if (args.len() == 3 || args.len() == 4) && args[1] == "number" {
+        // read every digit of the number on the image
+        let mut network = load_model();
+
+        println!("Oppening the image...");
+        let image = image::open(&args[2]).expect("The image provided is invalid or unreachable");
+        let side = (network.input_size() as f64).sqrt() as u32;
+        let number = recognition::recognize_number(&mut network, &image, side, side);
+
+        println!("Predicted number: {}", number.value());
+        for digit in &number.digits {
+            println!(
+                "  {} ({:.2}%) at x: {} y: {} size: {}x{}",
+                digit.digit,
+                digit.confidence * 100.0,
+                digit.bbox.x,
+                digit.bbox.y,
+                digit.bbox.width,
+                digit.bbox.height,
+            );
+        }
+
+        if let Some(output_path) = args.get(3) {
+            annotation::annotate(&image, &number)
+                .save(output_path)
+                .expect("Can't save the annotated image");
+            println!("Annotated image saved at location: {}", output_path);
+        }
+    } else if args.len() == 4 && args[1] == "batch" {
+        // classify every image of the directory
+        let mut network = load_model();
+
+        let side = (network.input_size() as f64).sqrt() as u32;
+        let reports = batch::classify_directory(&mut network, Path::new(&args[2]), side, side)
+            .expect("The directory provided is invalid or unreachable");
+
+        let mut report_file = File::create(&args[3]).expect("Can't create the report file");
+        if args[3].ends_with(".json") {
+            batch::write_json(&reports, &mut report_file)
+        } else {
+            batch::write_csv(&reports, &mut report_file)
+        }
+        .expect("Can't write the report");
+
+        let errors = reports
+            .iter()
+            .filter(|report| report.result.is_err())
+            .count();
+        println!(
+            "{} files classified ({} errors), report saved at location: {}",
+            reports.len(),
+            errors,
+            args[3]
+        );
+    } else if (args.len() == 4 || args.len() == 5) && args[1] == "export" {
+        // write a model as a NumPy archive or an ONNX model depending on the extension, as JSON
+        // otherwise, indented if "pretty" follows the paths
+        let mut network = Network::load_from_file(&args[2], &1.0)
+            .unwrap_or_else(|error| panic!("Can't load the model: {}", error));
+        let pretty = args.get(4).is_some_and(|arg| arg == "pretty");
+
+        let content = if args[3].ends_with(".npz") {
+            network.to_npz()
+        } else if args[3].ends_with(".onnx") {
+            network
+                .to_onnx()
+                .unwrap_or_else(|error| panic!("Can't export the model: {}", error))
+        } else {
+            network
+                .to_json(pretty)
+                .unwrap_or_else(|error| panic!("Can't export the model: {}", error))
+                .into_bytes()
+        };
+        fs::write(&args[3], content).expect("Can't write the exported model");
+        println!("Model exported at location: {}", args[3]);
+
+        if args[3].ends_with(".onnx") {
+            // read the written file back and compare the graph with the network
+            let bytes = fs::read(&args[3]).expect("Can't read the exported model");
+            match network.check_onnx(&bytes) {
+                Ok((node_count, difference)) => println!(
+                    "ONNX graph of {} nodes, largest difference with the network: {:e} | {}",
+                    node_count,
+                    difference,
+                    if difference < model_onnx::TOLERANCE {
+                        "ok"
+                    } else {
+                        "WRONG"
+                    }
+                ),
+                Err(error) => println!("The exported model is invalid: {}", error),
+            }
+        }
+    } else if args.len() >= 4 && args[1] == "import" {
+        // save a JSON model or a NumPy archive in the binary format
+        // activations (hidden layers then output layer) can follow the paths to replace the
+        // ones of the imported model
+        let bytes = fs::read(&args[2]).expect("The file provided is invalid or unreachable");
+        let mut network = if args[2].ends_with(".npz") {
+            Network::from_npz(&bytes, &1.0)
+        } else {
+            Network::from_json(&String::from_utf8_lossy(&bytes), &1.0)
+        }
+        .unwrap_or_else(|error| panic!("Can't import the model: {}", error));
+
+        if args.len() > 4 {
+            let layer_activations: Vec<Activation> =
+                args[4..].iter().map(|arg| parse_activation(arg)).collect();
+            let layer_count = network.layer_count();
+            network.set_activations(layer_activations_for(&layer_activations, layer_count));
+        }
+
+        match network.save_to_file(&args[3]) {
+            Ok(()) => println!("File saved at location: {}", args[3]),
+            Err(error) => println!("Can't save the model: {}", error),
+        }
+    } else if args.len() >= 4 && args[1] == "check" {
+        // compare backpropagation with finite differences on a random network
+        // a loss name then activations (hidden layers then output layer) can precede the sizes
+        let (loss, arguments) = match losses::from_name(&args[2]) {
+            Some(loss) => (loss, &args[3..]),
+            None => (losses::from_name("mse").unwrap(), &args[2..]),
+        };
+        let mut layer_activations = Vec::new();
+        let mut layers_struct = Vec::<usize>::new();
+        for arg in arguments {
+            match arg.parse() {
+                Ok(size) => layers_struct.push(size),
+                Err(_) => layer_activations.push(parse_activation(arg)),
+            }
+        }
+        if layers_struct.len() < 2 || layers_struct.contains(&0) {
+            println!(
+                "Invalid command : the network needs an input and an output size, none being 0\n  cargo run check [loss] [activation...] input_size hidden_size... output_size"
+            );
+            return;
+        }
+        let mut network = Network::with_activations(
+            &layers_struct,
+            &1.0,
+            layer_activations_for(&layer_activations, layers_struct.len() - 1),
+        );
+        network.set_loss(loss);
+
+        let batch_size = 4;
+        let inputs = Matrix::random(batch_size, layers_struct[0]);
+        let targets =
+            Matrix::random(batch_size, *layers_struct.last().unwrap()).map(&|x| (x + 1.0) / 2.0);
+
+        for check in network.gradient_check(&inputs, &targets, 1e-5) {
+            println!(
+                "Layer {}: weights error: {:e} | biases error: {:e} | {}",
+                check.layer,
+                check.weights_error,
+                check.biases_error,
+                if check.is_valid() { "ok" } else { "WRONG" }
+            );
+        }
     } else if args.len() == 4 && args[1] == "train" {
         println!("Oppening the training set...");
 
         let (input_model_path, output_model_path) = ask_model_in_out();
 
         // open the image training file
-        let mut f_images =
-            File::open(&args[2]).expect("The file provided is invalid or unreachable");
+        let f_images = File::open(&args[2]).expect("The file provided is invalid or unreachable");
 
         // open the label training file
-        let mut f_labels =
-            File::open(&args[3]).expect("The file provided is invalid or unreachable");
+        let f_labels = File::open(&args[3]).expect("The file provided is invalid or unreachable");
 
         println!("Getting the dataset meta...");
-        let training_data = TrainingData::new(&mut f_images, &mut f_labels);
+        let mut training_data = TrainingData::new(f_images, f_labels)
+            .unwrap_or_else(|error| panic!("Can't read the dataset: {}", error));
 
         println!(
             "Images:\n  Magic number: {} | Count: {} | Size: {}x{}",
@@ -45,13 +236,26 @@ fn main() {
         );
 
         println!("Loading the training data...");
-        let mut inputs = Vec::<Vec<f64>>::new();
-        let mut targets = Vec::<Vec<f64>>::new();
+        let mut inputs = Vec::<Vec<f64>>::with_capacity(training_data.label_count as usize);
+        let mut targets = Vec::<Vec<f64>>::with_capacity(training_data.label_count as usize);
 
-        for (input, target) in training_data.clone().into_iter().take(100000) {
+        for sample in training_data.by_ref() {
+            let (input, target) =
+                sample.unwrap_or_else(|error| panic!("Can't read the dataset: {}", error));
             inputs.push(input);
             targets.push(target);
         }
+        training_data.finish().unwrap_or_else(|error| {
+            panic!(
+                "The images file doesn't end after the last image: {}",
+                error
+            )
+        });
+
+        // keep the last tenth of the samples to measure the cost seen by the schedule
+        let validation_count = inputs.len() / 10;
+        let validation_inputs = inputs.split_off(inputs.len() - validation_count);
+        let validation_targets = targets.split_off(targets.len() - validation_count);
 
         let layers_struct = vec![
             (training_data.rows_count * training_data.cols_count) as usize,
@@ -59,28 +263,151 @@ fn main() {
             10,
         ];
 
-        let learning_rate = 1.0;
-        let activation_function = activations::SIGMOID;
+        let optimizer = ask_question(
+            "Optimizer (sgd, momentum, nesterov, adagrad, rmsprop, adam, adamw), enter for sgd: ",
+        );
+        let optimizer = optimizers::from_name(if optimizer.is_empty() {
+            "sgd"
+        } else {
+            &optimizer
+        })
+        .expect("Unknown optimizer");
+        let learning_rate = ask_question("Learning rate (enter for 1.0): ");
+        let learning_rate = if learning_rate.is_empty() {
+            1.0
+        } else {
+            learning_rate
+                .parse()
+                .expect("The learning rate must be a number")
+        };
+        let steps = 10000;
+        let schedule_name = ask_question(
+            "Learning rate schedule (constant, step, exponential, cosine, plateau), enter for constant: ",
+        );
+        let mut schedule = schedules::from_name(
+            if schedule_name.is_empty() {
+                "constant"
+            } else {
+                &schedule_name
+            },
+            steps,
+        )
+        .expect("Unknown learning rate schedule");
+        // a pass over the data being longer than the training, the plateau is measured more often
+        let validation_interval = (schedule_name == "plateau").then(|| {
+            let interval = ask_question("Batches between two validations (enter for 500): ");
+            if interval.is_empty() {
+                500
+            } else {
+                interval
+                    .parse()
+                    .expect("The validation interval must be a number of batches")
+            }
+        });
+        let warmup = ask_question("Warmup batches (enter for none): ");
+        if !warmup.is_empty() {
+            let warmup = warmup
+                .parse()
+                .expect("The warmup must be a number of batches");
+            schedule = Box::new(schedules::Warmup::new(warmup, schedule));
+        }
+        let loss_name = ask_question("Loss (mse, mae, huber, bce, softmax), enter for mse: ");
+        let loss: Box<dyn Loss> = if loss_name == "softmax" {
+            let smoothing = ask_question("Label smoothing (enter for none): ");
+            Box::new(losses::SoftmaxCrossEntropy::new(if smoothing.is_empty() {
+                0.0
+            } else {
+                smoothing
+                    .parse()
+                    .expect("The label smoothing must be a number")
+            }))
+        } else {
+            losses::from_name(if loss_name.is_empty() {
+                "mse"
+            } else {
+                &loss_name
+            })
+            .expect("Unknown loss")
+        };
 
-        let mut network = if input_model_path == "" {
-            Network::new(&layers_struct, &learning_rate, activation_function)
+        // a loaded model keeps the activations and the normalization it was trained with
+        let mut network = if input_model_path.is_empty() {
+            let hidden_activation =
+                ask_question("Hidden layers activation, name[:parameter] (enter for sigmoid): ");
+            let output_activation =
+                ask_question("Output layer activation, name[:parameter] (enter for sigmoid): ");
+            let layer_activations: Vec<Activation> = [hidden_activation, output_activation]
+                .iter()
+                .map(|activation| {
+                    if activation.is_empty() {
+                        Activation::Sigmoid
+                    } else {
+                        parse_activation(activation)
+                    }
+                })
+                .collect();
+            let normalization_name = ask_question(
+                "Input normalization (none, unit, symmetric, standardize), enter for none: ",
+            );
+            // computed from the training samples only, the validation ones being unseen data
+            let normalization = if normalization_name.is_empty() {
+                Normalization::None
+            } else {
+                normalization::from_name(&normalization_name, &inputs)
+                    .expect("Unknown normalization")
+            };
+            let activations = layer_activations_for(&layer_activations, layers_struct.len() - 1);
+            let seed = ask_question(&format!(
+                "Seed of the initial weights, batches of {} samples running on one thread (enter for a random one): ",
+                BATCH_SIZE
+            ));
+            let mut network = if seed.is_empty() {
+                Network::with_activations(&layers_struct, &learning_rate, activations)
+            } else {
+                let seed = seed.parse().expect("The seed must be a positive integer");
+                Network::new_seeded(&layers_struct, &learning_rate, activations, seed)
+            };
+            network.set_normalization(normalization);
+            network
         } else {
             println!("Loading model from: {}...", input_model_path);
-            Network::load_from_file(&input_model_path, &learning_rate, activation_function)
+            Network::load_from_file(&input_model_path, &learning_rate)
+                .unwrap_or_else(|error| panic!("Can't load the model: {}", error))
         };
 
-        network.train_with_batch(&inputs, &targets, 10000, 5); // -> 58% // -> 70%
-                                                               // network.train(&inputs, &targets, 10);
+        network.set_optimizer(optimizer);
+        network.set_schedule(schedule);
+        network.set_loss(loss);
+        network.set_validation_data(&validation_inputs, &validation_targets);
+        if let Some(interval) = validation_interval {
+            network.set_validation_interval(interval);
+        }
+        network.set_thread_count(thread::available_parallelism().map_or(1, |count| count.get()));
+        network.train_with_batch(&inputs, &targets, steps, BATCH_SIZE); // -> 58% // -> 70%
+                                                                        // network.train(&inputs, &targets, 10);
+
+        println!("Training accuracy:");
+        let mut accuracy = network.test_accuracy(&inputs, &targets);
+        if validation_count > 0 {
+            println!("Validation accuracy:");
+            accuracy = network.test_accuracy(&validation_inputs, &validation_targets);
+        }
 
-        network.test_accuracy(&inputs, &targets);
+        let metadata = network.metadata_mut();
+        metadata.dataset = Path::new(&args[2])
+            .file_name()
+            .map_or(args[2].clone(), |name| name.to_string_lossy().into_owned());
+        metadata.accuracy = accuracy;
 
-        if output_model_path != "" {
-            network.save(&output_model_path).ok();
-            println!("File saved at location: {}", output_model_path);
+        if !output_model_path.is_empty() {
+            match network.save_to_file(&output_model_path) {
+                Ok(()) => println!("File saved at location: {}", output_model_path),
+                Err(error) => println!("Can't save the model: {}", error),
+            }
         }
     } else {
         println!(
-            "Invalid command : \n  cargo run train path/to/images_dataset path/to/labels_dataset\n  cargo run path/to/image"
+            "Invalid command : \n  cargo run train path/to/images_dataset path/to/labels_dataset\n  cargo run path/to/image\n  cargo run number path/to/image [path/to/annotated_image]\n  cargo run check [loss] [activation...] input_size hidden_size... output_size\n  cargo run batch path/to/directory path/to/report.(csv|json)\n  cargo run export path/to/model path/to/model.(json|npz|onnx) [pretty]\n  cargo run import path/to/model.(json|npz) path/to/model [activation...]"
         )
     }
 
@@ -100,6 +427,58 @@ fn main() {
         s
     }
 
+    fn load_model() -> Network {
+        let model_path = ask_question("Model path: ");
+
+        println!("Loading model from: {}...", model_path);
+        let network = Network::load_from_file(&model_path, &1.0)
+            .unwrap_or_else(|error| panic!("Can't load the model: {}", error));
+
+        let metadata = network.metadata();
+        if metadata.date != 0 {
+            println!(
+                "Trained on {} for {} batches ({:.2}% accuracy), last on {}",
+                metadata.dataset,
+                metadata.batches,
+                metadata.accuracy * 100.0,
+                metadata.formatted_date()
+            );
+        }
+        if *network.normalization() != Normalization::None {
+            println!("Inputs normalized with: {}", network.normalization().name());
+        }
+
+        network
+    }
+
+    /// Activation written `name` or `name:parameter`
+    fn parse_activation(text: &str) -> Activation {
+        text.parse().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// The first activations for the hidden layers (the last one repeated) and the last one for
+    /// the output layer, sigmoid everywhere if none is given
+    fn layer_activations_for(activations: &[Activation], layer_count: usize) -> Vec<Activation> {
+        let activations = if activations.is_empty() {
+            &[Activation::Sigmoid][..]
+        } else {
+            activations
+        };
+        let output = activations.last().unwrap().clone();
+        let hidden = &activations[..activations.len() - 1];
+
+        (0..layer_count - 1)
+            .map(|i| hidden.get(i).or(hidden.last()).unwrap_or(&output).clone())
+            .chain([output.clone()])
+            .collect()
+    }
+
+    fn load_image(path: &str, rows: u32, cols: u32) -> Vec<f64> {
+        let image = image::open(path).expect("The image provided is invalid or unreachable");
+
+        preprocessing::preprocess(&image, rows, cols)
+    }
+
     fn ask_model_in_out() -> (String, String) {
         // let _ = stdout().flush();
 
@@ -1,12 +1,17 @@
 use core::panic;
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Sub, SubAssign};
 
 use rand::{thread_rng, Rng};
 
+/// Size of the square tiles used by the matrix multiplication, small enough to stay in cache
+const BLOCK_SIZE: usize = 64;
+
 #[derive(Clone)]
 pub struct Matrix {
     pub rows: usize,
     pub cols: usize,
-    pub data: Vec<Vec<f64>>,
+    /// Row-major values: the element (i, j) is stored at `i * cols + j`
+    pub data: Vec<f64>,
 }
 
 impl Matrix {
@@ -15,22 +20,23 @@ impl Matrix {
         Matrix {
             rows,
             cols,
-            data: vec![vec![0.0; cols]; rows],
+            data: vec![0.0; rows * cols],
         }
     }
 
     /// Generate a random matrix
     pub fn random(rows: usize, cols: usize) -> Matrix {
-        let mut rng = thread_rng();
+        Matrix::random_with(rows, cols, &mut thread_rng())
+    }
 
+    /// Generate a random matrix from the given generator
+    pub fn random_with(rows: usize, cols: usize, rng: &mut impl Rng) -> Matrix {
         // create a matrix of zeros
         let mut res = Matrix::zeros(rows, cols);
 
         // randomize the values of the res matrix
-        for i in 0..rows {
-            for j in 0..cols {
-                res.data[i][j] = rng.gen::<f64>() * 2.0 - 1.0
-            }
+        for value in res.data.iter_mut() {
+            *value = rng.gen::<f64>() * 2.0 - 1.0
         }
 
         // return the random matrix
@@ -38,95 +44,223 @@ impl Matrix {
     }
 
     pub fn from(data: Vec<Vec<f64>>) -> Matrix {
+        let rows = data.len();
+        let cols = data[0].len();
+
         Matrix {
-            rows: data.len(),
-            cols: data[0].len(),
-            data,
+            rows,
+            cols,
+            data: data.into_iter().flatten().collect(),
+        }
+    }
+
+    /// Build a matrix from its row-major values
+    pub fn from_vec(rows: usize, cols: usize, data: Vec<f64>) -> Matrix {
+        if data.len() != rows * cols {
+            panic!("Attempted to create a matrix from data of incorrect length");
         }
+
+        Matrix { rows, cols, data }
+    }
+
+    /// Copy of the rows from `start` (included) to `end` (excluded)
+    pub fn rows_range(&self, start: usize, end: usize) -> Matrix {
+        Matrix::from_vec(
+            end - start,
+            self.cols,
+            self.data[start * self.cols..end * self.cols].to_vec(),
+        )
+    }
+
+    pub fn row(&self, i: usize) -> &[f64] {
+        &self.data[i * self.cols..(i + 1) * self.cols]
     }
 
     /// myltiply two matrix
-    pub fn multiply(&mut self, other: &Matrix) -> Matrix {
+    pub fn multiply(&self, other: &Matrix) -> Matrix {
+        let mut res = Matrix::zeros(self.rows, other.cols);
+        res.add_product(self, other);
+
+        // return the result matrix
+        res
+    }
+
+    /// Add the product of the `left` and `right` matrix to this one (fused multiply-add)
+    pub fn add_product(&mut self, left: &Matrix, right: &Matrix) {
         // check if the multiplication is possible between the two matrix
-        if self.cols != other.rows {
+        if left.cols != right.rows || self.rows != left.rows || self.cols != right.cols {
             panic!("Attempted to multiply by matrix of incorrect dimensions");
         }
 
-        let mut res = Matrix::zeros(self.rows, other.cols);
+        let kernels = Kernels::detect();
+        // a column vector is contiguous: use dot products instead of tiles
+        if right.cols == 1 {
+            for i in 0..left.rows {
+                self.data[i] += kernels.dot(left.row(i), &right.data);
+            }
+            return;
+        }
 
-        // multiply the two matrix
-        for i in 0..self.rows {
-            for j in 0..other.cols {
-                let mut sum = 0.0;
-                for k in 0..self.cols {
-                    sum += self.data[i][k] * other.data[k][j];
+        // multiply the two matrix tile by tile
+        for i0 in (0..left.rows).step_by(BLOCK_SIZE) {
+            for k0 in (0..left.cols).step_by(BLOCK_SIZE) {
+                for j0 in (0..right.cols).step_by(BLOCK_SIZE) {
+                    let j1 = (j0 + BLOCK_SIZE).min(right.cols);
+                    for i in i0..(i0 + BLOCK_SIZE).min(left.rows) {
+                        for k in k0..(k0 + BLOCK_SIZE).min(left.cols) {
+                            let value = left.data[i * left.cols + k];
+                            kernels.axpy(
+                                value,
+                                &right.data[k * right.cols + j0..k * right.cols + j1],
+                                &mut self.data[i * right.cols + j0..i * right.cols + j1],
+                            );
+                        }
+                    }
                 }
-                res.data[i][j] = sum;
             }
         }
+    }
 
-        // return the result matrix
-        res
+    /// Add `left * right^T` to this matrix, the rows of both matrix are multiplied together
+    pub fn add_product_transposed(&mut self, left: &Matrix, right: &Matrix) {
+        if left.cols != right.cols || self.rows != left.rows || self.cols != right.rows {
+            panic!("Attempted to multiply by matrix of incorrect dimensions");
+        }
+
+        let kernels = Kernels::detect();
+        for i in 0..left.rows {
+            for j in 0..right.rows {
+                self.data[i * self.cols + j] += kernels.dot(left.row(i), right.row(j));
+            }
+        }
     }
 
-    /// Add two matrix
-    pub fn add(&mut self, other: &Matrix) -> Matrix {
-        // check if the addition is possible between the two matrix
-        if self.rows != other.rows || self.cols != other.cols {
-            panic!("Attempted to add by matrix of incorrect dimensions");
+    /// Add `left^T * right` to this matrix, each row of `right` is scaled by a row of `left`
+    pub fn add_transposed_product(&mut self, left: &Matrix, right: &Matrix) {
+        if left.rows != right.rows || self.rows != left.cols || self.cols != right.cols {
+            panic!("Attempted to multiply by matrix of incorrect dimensions");
         }
 
-        // add the two matrix
-        let mut res = Matrix::zeros(self.rows, self.cols);
+        let kernels = Kernels::detect();
+        for k in 0..left.rows {
+            for i in 0..left.cols {
+                let value = left.data[k * left.cols + i];
+                kernels.axpy(
+                    value,
+                    right.row(k),
+                    &mut self.data[i * self.cols..(i + 1) * self.cols],
+                );
+            }
+        }
+    }
+
+    /// Add the values of the vector to every row of the matrix
+    pub fn add_to_rows(&mut self, vector: &[f64]) {
+        if vector.len() != self.cols {
+            panic!("Attempted to add a vector of incorrect length");
+        }
+
+        let kernels = Kernels::detect();
+        for row in self.data.chunks_mut(self.cols) {
+            kernels.axpy(1.0, vector, row);
+        }
+    }
 
+    /// Column vector holding the sum of every row of the matrix
+    pub fn sum_rows(&self) -> Matrix {
+        let mut res = Matrix::zeros(self.cols, 1);
+        let kernels = Kernels::detect();
         for i in 0..self.rows {
-            for j in 0..self.cols {
-                res.data[i][j] = self.data[i][j] + other.data[i][j];
-            }
+            kernels.axpy(1.0, self.row(i), &mut res.data);
+        }
+        res
+    }
+
+    /// Multiply the matrix element by element with the other one
+    pub fn multiply_elements(&mut self, other: &Matrix) {
+        if self.rows != other.rows || self.cols != other.cols {
+            panic!("Attempted to multiply by matrix of incorrect dimensions");
         }
 
+        for (value, other) in self.data.iter_mut().zip(&other.data) {
+            *value *= other;
+        }
+    }
+
+    /// Add two matrix
+    pub fn add(&self, other: &Matrix) -> Matrix {
+        let mut res = self.clone();
+        res.add_assign(other);
+
+        // return the result matrix
+        res
+    }
+
+    /// Add the other matrix to this one
+    pub fn add_assign(&mut self, other: &Matrix) {
+        self.add_scaled(1.0, other);
+    }
+
+    pub fn substract(&self, other: &Matrix) -> Matrix {
+        let mut res = self.clone();
+        res.substract_assign(other);
+
         // return the result matrix
         res
     }
 
-    pub fn substract(&mut self, other: &Matrix) -> Matrix {
-        // check if the substraction is possible between the two matrix
+    /// Substract the other matrix to this one
+    pub fn substract_assign(&mut self, other: &Matrix) {
+        self.add_scaled(-1.0, other);
+    }
+
+    /// Add `factor * other` to this matrix without allocating
+    pub fn add_scaled(&mut self, factor: f64, other: &Matrix) {
+        // check if the addition is possible between the two matrix
         if self.rows != other.rows || self.cols != other.cols {
-            panic!("Attempted to substract by matrix of incorrect dimensions");
+            panic!("Attempted to add by matrix of incorrect dimensions");
         }
 
-        // substract the other matrix to the actual one
-        let mut res = Matrix::zeros(self.rows, self.cols);
+        Kernels::detect().axpy(factor, &other.data, &mut self.data);
+    }
 
-        for i in 0..self.rows {
-            for j in 0..self.cols {
-                res.data[i][j] = self.data[i][j] - other.data[i][j];
-            }
+    /// Multiply every value of the matrix by the factor
+    pub fn scale_in_place(&mut self, factor: f64) {
+        for value in self.data.iter_mut() {
+            *value *= factor;
         }
+    }
 
-        // return the result matrix
-        res
+    /// Set every value of the matrix
+    pub fn fill(&mut self, value: f64) {
+        self.data.fill(value);
     }
 
     // map a function to edit the matrix
-    pub fn map(&mut self, function: &dyn Fn(f64) -> f64) -> Matrix {
-        Matrix::from(
-            (self.data)
-                .clone()
-                .into_iter()
-                .map(|row| row.into_iter().map(|value| function(value)).collect())
-                .collect(),
-        )
+    pub fn map(&self, function: &dyn Fn(f64) -> f64) -> Matrix {
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().map(|value| function(*value)).collect(),
+        }
+    }
+
+    /// Apply the function to every value of the matrix
+    pub fn map_in_place(&mut self, function: &dyn Fn(f64) -> f64) {
+        for value in self.data.iter_mut() {
+            *value = function(*value);
+        }
     }
 
     /// transpose the matrix
-    pub fn transpose(&mut self) -> Matrix {
+    #[allow(unused)]
+    pub fn transpose(&self) -> Matrix {
         let mut res = Matrix::zeros(self.cols, self.rows);
 
         // transpose into the res matrix
         for i in 0..self.rows {
             for j in 0..self.cols {
-                res.data[j][i] = self.data[i][j];
+                res.data[j * self.rows + i] = self.data[i * self.cols + j];
             }
         }
 
@@ -134,12 +268,161 @@ impl Matrix {
         res
     }
 
-    pub fn print_size(&self, name: &str) -> () {
-        println!(
-            "{} matrix size: {}x{}",
-            name,
-            self.data.len(),
-            self.data[0].len()
-        );
+    #[allow(unused)]
+    pub fn print_size(&self, name: &str) {
+        println!("{} matrix size: {}x{}", name, self.rows, self.cols);
+    }
+}
+
+impl Index<(usize, usize)> for Matrix {
+    type Output = f64;
+
+    fn index(&self, (i, j): (usize, usize)) -> &f64 {
+        &self.data[i * self.cols + j]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut f64 {
+        &mut self.data[i * self.cols + j]
+    }
+}
+
+impl Add<&Matrix> for &Matrix {
+    type Output = Matrix;
+
+    fn add(self, other: &Matrix) -> Matrix {
+        Matrix::add(self, other)
+    }
+}
+
+impl Sub<&Matrix> for &Matrix {
+    type Output = Matrix;
+
+    fn sub(self, other: &Matrix) -> Matrix {
+        self.substract(other)
+    }
+}
+
+/// Matrix product
+impl Mul<&Matrix> for &Matrix {
+    type Output = Matrix;
+
+    fn mul(self, other: &Matrix) -> Matrix {
+        self.multiply(other)
+    }
+}
+
+impl Mul<f64> for &Matrix {
+    type Output = Matrix;
+
+    fn mul(self, factor: f64) -> Matrix {
+        let mut res = self.clone();
+        res.scale_in_place(factor);
+        res
+    }
+}
+
+impl AddAssign<&Matrix> for Matrix {
+    fn add_assign(&mut self, other: &Matrix) {
+        Matrix::add_assign(self, other)
+    }
+}
+
+impl SubAssign<&Matrix> for Matrix {
+    fn sub_assign(&mut self, other: &Matrix) {
+        self.substract_assign(other)
+    }
+}
+
+/// Vector operations, using the AVX instructions when the cpu has them. The cpu features are
+/// detected once per matrix operation rather than for each row.
+#[derive(Clone, Copy)]
+struct Kernels {
+    #[cfg_attr(not(target_arch = "x86_64"), allow(unused))]
+    avx: bool,
+}
+
+impl Kernels {
+    fn detect() -> Kernels {
+        #[cfg(target_arch = "x86_64")]
+        let avx = is_x86_feature_detected!("avx") && is_x86_feature_detected!("fma");
+        #[cfg(not(target_arch = "x86_64"))]
+        let avx = false;
+        Kernels { avx }
+    }
+
+    /// y += alpha * x
+    fn axpy(self, alpha: f64, x: &[f64], y: &mut [f64]) {
+        #[cfg(target_arch = "x86_64")]
+        if self.avx {
+            // SAFETY: `detect` checked that the cpu supports the features enabled on the function
+            unsafe { simd::axpy(alpha, x, y) };
+            return;
+        }
+
+        for (y, x) in y.iter_mut().zip(x) {
+            *y += alpha * x;
+        }
+    }
+
+    /// Sum of the products of the elements of x and y
+    fn dot(self, x: &[f64], y: &[f64]) -> f64 {
+        #[cfg(target_arch = "x86_64")]
+        if self.avx {
+            // SAFETY: `detect` checked that the cpu supports the features enabled on the function
+            return unsafe { simd::dot(x, y) };
+        }
+
+        x.iter().zip(y).map(|(x, y)| x * y).sum()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+
+    /// Number of f64 in an AVX register
+    const LANES: usize = 4;
+
+    #[target_feature(enable = "avx,fma")]
+    pub unsafe fn axpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+        let len = x.len().min(y.len());
+        let chunks = len / LANES;
+        let alpha_lanes = _mm256_set1_pd(alpha);
+
+        for chunk in 0..chunks {
+            let x_ptr = x.as_ptr().add(chunk * LANES);
+            let y_ptr = y.as_mut_ptr().add(chunk * LANES);
+            let res = _mm256_fmadd_pd(alpha_lanes, _mm256_loadu_pd(x_ptr), _mm256_loadu_pd(y_ptr));
+            _mm256_storeu_pd(y_ptr, res);
+        }
+
+        for i in chunks * LANES..len {
+            y[i] += alpha * x[i];
+        }
+    }
+
+    #[target_feature(enable = "avx,fma")]
+    pub unsafe fn dot(x: &[f64], y: &[f64]) -> f64 {
+        let len = x.len().min(y.len());
+        let chunks = len / LANES;
+        let mut sum_lanes = _mm256_setzero_pd();
+
+        for chunk in 0..chunks {
+            let x_lanes = _mm256_loadu_pd(x.as_ptr().add(chunk * LANES));
+            let y_lanes = _mm256_loadu_pd(y.as_ptr().add(chunk * LANES));
+            sum_lanes = _mm256_fmadd_pd(x_lanes, y_lanes, sum_lanes);
+        }
+
+        let mut lanes = [0.0; LANES];
+        _mm256_storeu_pd(lanes.as_mut_ptr(), sum_lanes);
+        let mut sum: f64 = lanes.iter().sum();
+
+        for i in chunks * LANES..len {
+            sum += x[i] * y[i];
+        }
+
+        sum
     }
 }
@@ -1,4 +1,16 @@
 pub mod activations;
+pub mod gradient_check;
 pub mod layer;
+pub mod losses;
+pub mod metadata;
+pub mod model_file;
+pub mod model_json;
+pub mod model_npz;
+pub mod model_onnx;
+#[allow(clippy::module_inception)]
 pub mod network;
+pub mod normalization;
+pub mod optimizers;
+pub mod prediction;
+pub mod schedules;
 pub mod training_data;
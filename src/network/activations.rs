@@ -1,18 +1,282 @@
-use std::f64::consts::E;
+use std::{
+    f64::consts::{E, PI},
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use crate::matrix::matrix::Matrix;
 
-#[derive(Clone)]
-pub struct Activation<'a> {
-    pub function: &'a dyn Fn(f64) -> f64,
-    pub derivative: &'a dyn Fn(f64) -> f64,
+/// Function applied to the weighted outputs of a layer.
+/// Written `name` or `name:parameter` (see `Display` and `FromStr`), which is how the activations
+/// are saved with the models.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Activation {
+    Sigmoid,
+    Tanh,
+    Relu,
+    /// Slope of the negative part
+    LeakyRelu(f64),
+    /// Value reached by the negative part at -infinity (alpha)
+    Elu(f64),
+    Selu,
+    /// tanh approximation of x * P(X <= x) for X ~ N(0, 1)
+    Gelu,
+    /// Also called SiLU: x * sigmoid(x)
+    Swish,
+    Softplus,
+    Linear,
+    /// Probabilities of the classes: the exponentials of a sample divided by their sum
+    Softmax,
+    /// Function added with `register`, identified by its name
+    Custom(String),
 }
 
-pub const SIGMOID: Activation = Activation {
-    function: &|x| 1.0 / (1.0 + E.powf(-x)),
-    derivative: &|x| (SIGMOID.function)(x) * (1.0 - (SIGMOID.function)(x)),
-};
+/// Element-wise function, or its derivative
+pub type Function = Arc<dyn Fn(f64) -> f64 + Send + Sync>;
+
+/// Name, function and derivative of the activations added with `register`
+static CUSTOM_ACTIVATIONS: RwLock<Vec<(String, Function, Function)>> = RwLock::new(Vec::new());
 
+/// Add an element-wise activation, usable through `Activation::Custom(name)` and loadable from
+/// saved models (it has to be registered before loading them).
+/// Registering a name again replaces the previous functions.
 #[allow(unused)]
-pub const RELU: Activation = Activation {
-    function: &|x| if x > 0.0 { x } else { 0.01 * x },
-    derivative: &|x| if x > 0.0 { 1.0 } else { 0.01 },
-};
+pub fn register(
+    name: &str,
+    function: impl Fn(f64) -> f64 + Send + Sync + 'static,
+    derivative: impl Fn(f64) -> f64 + Send + Sync + 'static,
+) {
+    assert!(
+        from_name(name, None).is_none_or(|activation| matches!(activation, Activation::Custom(_))),
+        "{} is a predefined activation",
+        name
+    );
+    assert!(!name.contains(':'), "The activation name can't contain ':'");
+    assert!(
+        name.len() <= 255,
+        "The activation name is too long to be saved"
+    );
+
+    let mut custom_activations = CUSTOM_ACTIVATIONS.write().unwrap();
+    custom_activations.retain(|(custom_name, _, _)| custom_name != name);
+    custom_activations.push((name.to_string(), Arc::new(function), Arc::new(derivative)));
+}
+
+/// Activation matching the name, `parameter` replacing its default one
+pub fn from_name(name: &str, parameter: Option<f64>) -> Option<Activation> {
+    let activation = match name {
+        "sigmoid" => Activation::Sigmoid,
+        "tanh" => Activation::Tanh,
+        "relu" => Activation::Relu,
+        "leaky_relu" => Activation::LeakyRelu(parameter.unwrap_or(0.01)),
+        "elu" => Activation::Elu(parameter.unwrap_or(1.0)),
+        "selu" => Activation::Selu,
+        "gelu" => Activation::Gelu,
+        "swish" => Activation::Swish,
+        "softplus" => Activation::Softplus,
+        "linear" => Activation::Linear,
+        "softmax" => Activation::Softmax,
+        _ => {
+            let custom_activations = CUSTOM_ACTIVATIONS.read().unwrap();
+            if !custom_activations
+                .iter()
+                .any(|(custom_name, _, _)| custom_name == name)
+            {
+                return None;
+            }
+            Activation::Custom(name.to_string())
+        }
+    };
+
+    Some(activation)
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + E.powf(-x))
+}
+
+pub const SELU_SCALE: f64 = 1.050_700_987_355_480_5;
+pub const SELU_ALPHA: f64 = 1.673_263_242_354_377_3;
+
+impl Activation {
+    pub fn name(&self) -> &str {
+        match self {
+            Activation::Sigmoid => "sigmoid",
+            Activation::Tanh => "tanh",
+            Activation::Relu => "relu",
+            Activation::LeakyRelu(_) => "leaky_relu",
+            Activation::Elu(_) => "elu",
+            Activation::Selu => "selu",
+            Activation::Gelu => "gelu",
+            Activation::Swish => "swish",
+            Activation::Softplus => "softplus",
+            Activation::Linear => "linear",
+            Activation::Softmax => "softmax",
+            Activation::Custom(name) => name,
+        }
+    }
+
+    /// Parameter of the activation, 0 for the ones without any
+    pub fn parameter(&self) -> f64 {
+        match self {
+            Activation::LeakyRelu(parameter) | Activation::Elu(parameter) => *parameter,
+            _ => 0.0,
+        }
+    }
+
+    /// Element-wise function and derivative (exponential and nothing for the softmax, which
+    /// is then normalized by `apply`)
+    pub fn functions(&self) -> (Function, Function) {
+        match *self {
+            Activation::Sigmoid => (
+                Arc::new(sigmoid),
+                Arc::new(|x| sigmoid(x) * (1.0 - sigmoid(x))),
+            ),
+            Activation::Tanh => (Arc::new(f64::tanh), Arc::new(|x| 1.0 - x.tanh().powi(2))),
+            Activation::Relu => (
+                Arc::new(|x| x.max(0.0)),
+                Arc::new(|x| if x > 0.0 { 1.0 } else { 0.0 }),
+            ),
+            Activation::LeakyRelu(slope) => (
+                Arc::new(move |x| if x > 0.0 { x } else { slope * x }),
+                Arc::new(move |x| if x > 0.0 { 1.0 } else { slope }),
+            ),
+            Activation::Elu(alpha) => (
+                Arc::new(move |x| if x > 0.0 { x } else { alpha * (x.exp() - 1.0) }),
+                Arc::new(move |x| if x > 0.0 { 1.0 } else { alpha * x.exp() }),
+            ),
+            Activation::Selu => (
+                Arc::new(|x| {
+                    if x > 0.0 {
+                        SELU_SCALE * x
+                    } else {
+                        SELU_SCALE * SELU_ALPHA * (x.exp() - 1.0)
+                    }
+                }),
+                Arc::new(|x| {
+                    if x > 0.0 {
+                        SELU_SCALE
+                    } else {
+                        SELU_SCALE * SELU_ALPHA * x.exp()
+                    }
+                }),
+            ),
+            Activation::Gelu => (
+                Arc::new(|x| {
+                    0.5 * x * (1.0 + ((2.0 / PI).sqrt() * (x + 0.044715 * x.powi(3))).tanh())
+                }),
+                Arc::new(|x| {
+                    let k = (2.0 / PI).sqrt();
+                    let t = (k * (x + 0.044715 * x.powi(3))).tanh();
+                    0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * k * (1.0 + 3.0 * 0.044715 * x * x)
+                }),
+            ),
+            Activation::Swish => (
+                Arc::new(|x| x * sigmoid(x)),
+                Arc::new(|x| sigmoid(x) + x * sigmoid(x) * (1.0 - sigmoid(x))),
+            ),
+            // ln(1 + e^x), written so the exponential can't overflow
+            Activation::Softplus => (
+                Arc::new(|x| x.max(0.0) + (-x.abs()).exp().ln_1p()),
+                Arc::new(sigmoid),
+            ),
+            Activation::Linear => (Arc::new(|x| x), Arc::new(|_| 1.0)),
+            Activation::Softmax => (Arc::new(f64::exp), Arc::new(|_| 0.0)),
+            Activation::Custom(ref name) => CUSTOM_ACTIVATIONS
+                .read()
+                .unwrap()
+                .iter()
+                .find(|(custom_name, _, _)| custom_name == name)
+                .map(|(_, function, derivative)| (function.clone(), derivative.clone()))
+                .unwrap_or_else(|| panic!("The activation {} isn't registered", name)),
+        }
+    }
+
+    /// Activate every sample of the batch (one sample per row)
+    pub fn apply(&self, weighted_outputs: &Matrix) -> Matrix {
+        let (function, _) = self.functions();
+        if !matches!(self, Activation::Softmax) {
+            return weighted_outputs.map(&*function);
+        }
+
+        let mut outputs = weighted_outputs.clone();
+        for sample in 0..outputs.rows {
+            // shift by the max so the exponentials can't overflow
+            let max = outputs
+                .row(sample)
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max);
+            let mut sum = 0.0;
+            for j in 0..outputs.cols {
+                outputs[(sample, j)] = function(outputs[(sample, j)] - max);
+                sum += outputs[(sample, j)];
+            }
+            for j in 0..outputs.cols {
+                outputs[(sample, j)] /= sum;
+            }
+        }
+        outputs
+    }
+
+    /// Derivative of the cost with respect to the weighted outputs, from its derivative with
+    /// respect to the activated outputs
+    pub fn backpropagate(&self, weighted_outputs: &Matrix, output_gradients: &Matrix) -> Matrix {
+        if !matches!(self, Activation::Softmax) {
+            let (_, derivative) = self.functions();
+            let mut node_values = output_gradients.clone();
+            node_values.multiply_elements(&weighted_outputs.map(&*derivative));
+            return node_values;
+        }
+
+        // softmax jacobian: s_k * (g_k - sum_j g_j * s_j)
+        let outputs = self.apply(weighted_outputs);
+        let mut node_values = output_gradients.clone();
+        for sample in 0..outputs.rows {
+            let dot: f64 = outputs
+                .row(sample)
+                .iter()
+                .zip(output_gradients.row(sample))
+                .map(|(output, gradient)| output * gradient)
+                .sum();
+            for j in 0..outputs.cols {
+                node_values[(sample, j)] =
+                    outputs[(sample, j)] * (output_gradients[(sample, j)] - dot);
+            }
+        }
+        node_values
+    }
+}
+
+impl fmt::Display for Activation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Activation::LeakyRelu(parameter) | Activation::Elu(parameter) => {
+                write!(f, "{}:{}", self.name(), parameter)
+            }
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+impl FromStr for Activation {
+    type Err = String;
+
+    /// Parse `name` or `name:parameter`
+    fn from_str(text: &str) -> Result<Activation, String> {
+        let (name, parameter) = match text.split_once(':') {
+            Some((name, parameter)) => (
+                name,
+                Some(
+                    parameter
+                        .parse()
+                        .map_err(|_| format!("Invalid activation parameter: {}", parameter))?,
+                ),
+            ),
+            None => (text, None),
+        };
+
+        from_name(name, parameter).ok_or_else(|| format!("Unknown activation: {}", name))
+    }
+}
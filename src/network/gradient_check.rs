@@ -0,0 +1,31 @@
+/// Distance between the backpropagated gradients of a layer and their finite differences estimate
+#[derive(Clone, Debug)]
+pub struct GradientCheck {
+    pub layer: usize,
+    pub weights_error: f64,
+    pub biases_error: f64,
+}
+
+impl GradientCheck {
+    /// Backpropagation is considered correct under this relative error
+    pub const TOLERANCE: f64 = 1e-5;
+
+    pub fn is_valid(&self) -> bool {
+        self.weights_error < GradientCheck::TOLERANCE
+            && self.biases_error < GradientCheck::TOLERANCE
+    }
+}
+
+/// |analytic - numeric| / (|analytic| + |numeric|), 0 if both gradients are null
+pub fn relative_error(analytic: &[f64], numeric: &[f64]) -> f64 {
+    let norm = |values: &mut dyn Iterator<Item = f64>| values.map(|v| v * v).sum::<f64>().sqrt();
+
+    let difference = norm(&mut analytic.iter().zip(numeric).map(|(a, n)| a - n));
+    let sum = norm(&mut analytic.iter().copied()) + norm(&mut numeric.iter().copied());
+
+    if sum == 0.0 {
+        0.0
+    } else {
+        difference / sum
+    }
+}
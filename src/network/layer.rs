@@ -1,108 +1,147 @@
+use rand::{thread_rng, Rng};
+
 use crate::matrix::matrix::Matrix;
 
-use super::activations;
+use super::{activations, losses::Loss, optimizers::Optimizer};
 
 #[derive(Clone)]
-pub struct Layer<'a> {
+pub struct Layer {
     pub weights: Matrix,
     pub biases: Matrix,
     pub size_in: usize,
     pub size_out: usize,
-    activation: activations::Activation<'a>,
-    inputs: Vec<f64>,
+    activation: activations::Activation,
+    /// Inputs of the last batch (batch x size_in)
+    inputs: Matrix,
+    /// Weighted outputs of the last batch, before the activation (batch x size_out)
     data: Matrix,
     cost_gradient_w: Matrix,
     cost_gradient_b: Matrix,
 }
 
-impl Layer<'_> {
+impl Layer {
     pub fn new(size_in: usize, size_out: usize, activation: activations::Activation) -> Layer {
+        Layer::new_with_rng(size_in, size_out, activation, &mut thread_rng())
+    }
+
+    /// Create a layer whose initial weights and biases are drawn from the generator
+    pub fn new_with_rng(
+        size_in: usize,
+        size_out: usize,
+        activation: activations::Activation,
+        rng: &mut impl Rng,
+    ) -> Layer {
         Layer {
-            weights: Matrix::random(size_out, size_in),
-            biases: Matrix::random(size_out, 1),
+            weights: Matrix::random_with(size_out, size_in, rng),
+            biases: Matrix::random_with(size_out, 1, rng),
             size_in,
             size_out,
             activation,
-            inputs: vec![],
+            inputs: Matrix::zeros(0, 0),
             data: Matrix::zeros(0, 0),
             cost_gradient_w: Matrix::zeros(size_out, size_in),
             cost_gradient_b: Matrix::zeros(size_out, 1),
         }
     }
 
-    pub fn compute_output(&mut self, inputs: Vec<f64>) -> Vec<f64> {
-        self.inputs = inputs.clone();
-        self.data = self
-            .weights
-            .multiply(&Matrix::from(vec![inputs]).transpose())
-            .add(&self.biases);
+    /// Compute the output of every sample of the batch, one sample per row
+    pub fn compute_output(&mut self, inputs: Matrix) -> Matrix {
+        // inputs * weights^T + biases
+        self.data = Matrix::zeros(inputs.rows, self.size_out);
+        self.data.add_product_transposed(&inputs, &self.weights);
+        self.data.add_to_rows(&self.biases.data);
+        self.inputs = inputs;
 
-        self.data.map(self.activation.function).transpose().data[0].clone()
+        self.activation.apply(&self.data)
     }
 
-    #[allow(unused)]
-    pub fn node_cost(output: f64, target: f64) -> f64 {
-        (output - target).powi(2)
+    pub fn activation(&self) -> &activations::Activation {
+        &self.activation
     }
 
-    pub fn node_cost_derivative(output: f64, target: f64) -> f64 {
-        2.0 * (output - target)
+    pub fn set_activation(&mut self, activation: activations::Activation) {
+        self.activation = activation;
     }
 
-    pub fn apply_gradients(&mut self, learning_rate: &f64) {
-        self.biases = self
-            .biases
-            .substract(&self.cost_gradient_b.map(&|x| x * learning_rate));
-        self.weights = self
-            .weights
-            .substract(&self.cost_gradient_w.map(&|x| x * learning_rate));
-        // TODO ???? wtf no substraction ????
+    /// Output of the last computation before the activation function (first sample of the batch)
+    pub fn weighted_output(&self) -> Vec<f64> {
+        self.data.row(0).to_vec()
     }
 
-    pub fn get_output_layer_node_value(&mut self, target: &Vec<f64>) -> Vec<f64> {
-        let mut node_values = vec![];
-        let activation_output = &self.data.map(self.activation.function).transpose().data[0];
-        let weighted_output = &self.data.transpose().data[0];
+    /// Average the gradients accumulated over the batch and let the optimizer update the
+    /// parameters of the layer (`index` is the position of the layer in the network)
+    pub fn apply_gradients(
+        &mut self,
+        optimizer: &mut dyn Optimizer,
+        index: usize,
+        learning_rate: f64,
+        batch_size: usize,
+    ) {
+        self.cost_gradient_w.scale_in_place(1.0 / batch_size as f64);
+        self.cost_gradient_b.scale_in_place(1.0 / batch_size as f64);
+
+        optimizer.update(
+            2 * index,
+            &mut self.weights,
+            &self.cost_gradient_w,
+            learning_rate,
+        );
+        optimizer.update(
+            2 * index + 1,
+            &mut self.biases,
+            &self.cost_gradient_b,
+            learning_rate,
+        );
+    }
 
-        for i in 0..self.size_out {
-            let cost_derivative = Layer::node_cost_derivative(activation_output[i], target[i]);
-            let activation_derivative = (self.activation.derivative)(weighted_output[i]);
-            node_values.push(activation_derivative * cost_derivative);
-        }
+    /// Loss of the last batch, when this is the output layer
+    pub fn cost(&self, targets: &Matrix, loss: &dyn Loss) -> f64 {
+        loss.cost(&self.data, &self.activation, targets)
+    }
 
-        node_values
+    /// Derivative of the cost with respect to the weighted outputs, for every sample of the batch
+    pub fn get_output_layer_node_value(&self, targets: &Matrix, loss: &dyn Loss) -> Matrix {
+        loss.node_values(&self.data, &self.activation, targets)
     }
 
+    /// Backpropagate the node values of the following layer through its weights
     pub fn get_hidden_layer_node_value(
-        &mut self,
+        &self,
         old_layer_weights: &Matrix,
-        old_node_values: &Vec<f64>,
-    ) -> Vec<f64> {
-        let mut node_values = vec![];
-
-        for i in 0..self.size_out {
-            let mut node_value = 0.0;
-            for j in 0..old_node_values.len() {
-                node_value += old_layer_weights.data[i][j] * old_node_values[j];
-            }
-            node_value *= (self.activation.derivative)(self.data.data[i][0]);
-            node_values.push(node_value);
-        }
+        old_node_values: &Matrix,
+    ) -> Matrix {
+        self.activation
+            .backpropagate(&self.data, &old_node_values.multiply(old_layer_weights))
+    }
 
-        node_values
+    /// Accumulate the gradients of every sample of the batch
+    pub fn update_gradients(&mut self, node_values: &Matrix) {
+        self.cost_gradient_w
+            .add_transposed_product(node_values, &self.inputs);
+        self.cost_gradient_b.add_assign(&node_values.sum_rows());
     }
 
-    pub fn update_gradients(&mut self, node_values: &Vec<f64>) {
-        for i in 0..self.size_out {
-            for j in 0..self.size_in {
-                self.cost_gradient_w.data[i][j] += self.inputs[j] * node_values[i];
-            }
-            self.cost_gradient_b.data[i][0] += node_values[i];
-        }
+    /// Accumulated gradients of the weights and of the biases
+    pub fn gradients(&self) -> (&Matrix, &Matrix) {
+        (&self.cost_gradient_w, &self.cost_gradient_b)
+    }
+
+    /// Take the parameters and the activation of another layer of the same size, reusing the
+    /// buffers of this one
+    pub fn copy_parameters(&mut self, other: &Layer) {
+        self.weights.data.copy_from_slice(&other.weights.data);
+        self.biases.data.copy_from_slice(&other.biases.data);
+        self.activation.clone_from(&other.activation);
+    }
+
+    /// Accumulate the gradients computed by a copy of this layer
+    pub fn add_gradients(&mut self, other: &Layer) {
+        self.cost_gradient_w.add_assign(&other.cost_gradient_w);
+        self.cost_gradient_b.add_assign(&other.cost_gradient_b);
     }
 
     pub fn clear_gradients(&mut self) {
-        self.cost_gradient_w = Matrix::zeros(self.size_out, self.size_in);
-        self.cost_gradient_b = Matrix::zeros(self.size_out, 1);
+        self.cost_gradient_w.fill(0.0);
+        self.cost_gradient_b.fill(0.0);
     }
 }
@@ -0,0 +1,277 @@
+use crate::matrix::matrix::Matrix;
+
+use super::activations::Activation;
+
+/// Cost of the output layer compared with the targets.
+/// Both methods receive the weighted outputs of the layer (before its activation, one sample per
+/// row) so a loss can be fused with its own output function.
+pub trait Loss: Send + Sync {
+    /// Loss summed over the samples of the batch
+    fn cost(&self, weighted_outputs: &Matrix, activation: &Activation, targets: &Matrix) -> f64;
+
+    /// Derivative of the cost with respect to the weighted outputs
+    fn node_values(
+        &self,
+        weighted_outputs: &Matrix,
+        activation: &Activation,
+        targets: &Matrix,
+    ) -> Matrix;
+
+    fn box_clone(&self) -> Box<dyn Loss>;
+}
+
+impl Clone for Box<dyn Loss> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Loss matching the name, with its usual hyperparameters
+pub fn from_name(name: &str) -> Option<Box<dyn Loss>> {
+    match name {
+        "mse" => Some(Box::new(SumSquaredError)),
+        "mae" => Some(Box::new(SumAbsoluteError)),
+        "huber" => Some(Box::new(Huber::new(1.0))),
+        "bce" => Some(Box::new(BinaryCrossEntropy)),
+        "softmax" => Some(Box::new(SoftmaxCrossEntropy::new(0.0))),
+        _ => None,
+    }
+}
+
+/// Keeps the logarithms of the binary cross-entropy finite
+const EPSILON: f64 = 1e-12;
+
+/// Sum of `cost(output, target)` over every output of the batch
+fn element_wise_cost(
+    weighted_outputs: &Matrix,
+    activation: &Activation,
+    targets: &Matrix,
+    cost: &dyn Fn(f64, f64) -> f64,
+) -> f64 {
+    activation
+        .apply(weighted_outputs)
+        .data
+        .iter()
+        .zip(&targets.data)
+        .map(|(output, target)| cost(*output, *target))
+        .sum()
+}
+
+/// Chain rule through the activation of `cost'(output, target)`
+fn element_wise_node_values(
+    weighted_outputs: &Matrix,
+    activation: &Activation,
+    targets: &Matrix,
+    cost_derivative: &dyn Fn(f64, f64) -> f64,
+) -> Matrix {
+    let mut output_gradients = activation.apply(weighted_outputs);
+
+    for (output, target) in output_gradients.data.iter_mut().zip(&targets.data) {
+        *output = cost_derivative(*output, *target);
+    }
+
+    activation.backpropagate(weighted_outputs, &output_gradients)
+}
+
+/// (output - target)², summed over the outputs of each sample rather than averaged, so the
+/// gradients don't shrink with the number of outputs (`mse` keeps its usual name)
+#[derive(Clone)]
+pub struct SumSquaredError;
+
+impl Loss for SumSquaredError {
+    fn cost(&self, weighted_outputs: &Matrix, activation: &Activation, targets: &Matrix) -> f64 {
+        element_wise_cost(weighted_outputs, activation, targets, &|output, target| {
+            (output - target).powi(2)
+        })
+    }
+
+    fn node_values(
+        &self,
+        weighted_outputs: &Matrix,
+        activation: &Activation,
+        targets: &Matrix,
+    ) -> Matrix {
+        element_wise_node_values(weighted_outputs, activation, targets, &|output, target| {
+            2.0 * (output - target)
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn Loss> {
+        Box::new(self.clone())
+    }
+}
+
+/// |output - target|, summed over the outputs of each sample like `SumSquaredError`
+#[derive(Clone)]
+pub struct SumAbsoluteError;
+
+impl Loss for SumAbsoluteError {
+    fn cost(&self, weighted_outputs: &Matrix, activation: &Activation, targets: &Matrix) -> f64 {
+        element_wise_cost(weighted_outputs, activation, targets, &|output, target| {
+            (output - target).abs()
+        })
+    }
+
+    fn node_values(
+        &self,
+        weighted_outputs: &Matrix,
+        activation: &Activation,
+        targets: &Matrix,
+    ) -> Matrix {
+        element_wise_node_values(weighted_outputs, activation, targets, &|output, target| {
+            if output > target {
+                1.0
+            } else if output < target {
+                -1.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn Loss> {
+        Box::new(self.clone())
+    }
+}
+
+/// Squared error under `delta`, absolute error above it
+#[derive(Clone)]
+pub struct Huber {
+    delta: f64,
+}
+
+impl Huber {
+    pub fn new(delta: f64) -> Huber {
+        Huber { delta }
+    }
+}
+
+impl Loss for Huber {
+    fn cost(&self, weighted_outputs: &Matrix, activation: &Activation, targets: &Matrix) -> f64 {
+        element_wise_cost(weighted_outputs, activation, targets, &|output, target| {
+            let error = (output - target).abs();
+            if error <= self.delta {
+                0.5 * error * error
+            } else {
+                self.delta * (error - 0.5 * self.delta)
+            }
+        })
+    }
+
+    fn node_values(
+        &self,
+        weighted_outputs: &Matrix,
+        activation: &Activation,
+        targets: &Matrix,
+    ) -> Matrix {
+        element_wise_node_values(weighted_outputs, activation, targets, &|output, target| {
+            (output - target).clamp(-self.delta, self.delta)
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn Loss> {
+        Box::new(self.clone())
+    }
+}
+
+/// -(target * ln(output) + (1 - target) * ln(1 - output)), for outputs between 0 and 1. The
+/// outputs are clamped into that range, where the cost stops changing and the gradient is zero.
+#[derive(Clone)]
+pub struct BinaryCrossEntropy;
+
+impl Loss for BinaryCrossEntropy {
+    fn cost(&self, weighted_outputs: &Matrix, activation: &Activation, targets: &Matrix) -> f64 {
+        element_wise_cost(weighted_outputs, activation, targets, &|output, target| {
+            let output = output.clamp(EPSILON, 1.0 - EPSILON);
+            -(target * output.ln() + (1.0 - target) * (1.0 - output).ln())
+        })
+    }
+
+    fn node_values(
+        &self,
+        weighted_outputs: &Matrix,
+        activation: &Activation,
+        targets: &Matrix,
+    ) -> Matrix {
+        element_wise_node_values(weighted_outputs, activation, targets, &|output, target| {
+            if !(EPSILON..=1.0 - EPSILON).contains(&output) {
+                return 0.0;
+            }
+            (output - target) / (output * (1.0 - output))
+        })
+    }
+
+    fn box_clone(&self) -> Box<dyn Loss> {
+        Box::new(self.clone())
+    }
+}
+
+/// Softmax of the weighted outputs followed by the categorical cross-entropy.
+/// The activation of the output layer is ignored: the softmax replaces it.
+/// With `smoothing`, the targets are mixed with a uniform distribution over the classes.
+#[derive(Clone)]
+pub struct SoftmaxCrossEntropy {
+    smoothing: f64,
+}
+
+impl SoftmaxCrossEntropy {
+    pub fn new(smoothing: f64) -> SoftmaxCrossEntropy {
+        SoftmaxCrossEntropy { smoothing }
+    }
+
+    fn smoothed(&self, target: f64, class_count: usize) -> f64 {
+        target * (1.0 - self.smoothing) + self.smoothing / class_count as f64
+    }
+}
+
+/// ln(softmax(logits)) computed without overflowing the exponentials
+fn log_softmax(logits: &[f64]) -> Vec<f64> {
+    let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let log_sum = logits
+        .iter()
+        .map(|logit| (logit - max).exp())
+        .sum::<f64>()
+        .ln();
+
+    logits.iter().map(|logit| logit - max - log_sum).collect()
+}
+
+impl Loss for SoftmaxCrossEntropy {
+    fn cost(&self, weighted_outputs: &Matrix, _: &Activation, targets: &Matrix) -> f64 {
+        let mut cost = 0.0;
+
+        for sample in 0..weighted_outputs.rows {
+            let log_probabilities = log_softmax(weighted_outputs.row(sample));
+            for (log_probability, target) in log_probabilities.iter().zip(targets.row(sample)) {
+                cost -= self.smoothed(*target, weighted_outputs.cols) * log_probability;
+            }
+        }
+
+        cost
+    }
+
+    fn node_values(&self, weighted_outputs: &Matrix, _: &Activation, targets: &Matrix) -> Matrix {
+        let mut node_values = weighted_outputs.clone();
+
+        // softmax(z) * sum(targets) - target, which is softmax(z) - target for one-hot targets
+        for sample in 0..weighted_outputs.rows {
+            let log_probabilities = log_softmax(weighted_outputs.row(sample));
+            let targets: Vec<f64> = targets
+                .row(sample)
+                .iter()
+                .map(|target| self.smoothed(*target, weighted_outputs.cols))
+                .collect();
+            let target_sum: f64 = targets.iter().sum();
+
+            for (class, log_probability) in log_probabilities.iter().enumerate() {
+                node_values[(sample, class)] = log_probability.exp() * target_sum - targets[class];
+            }
+        }
+
+        node_values
+    }
+
+    fn box_clone(&self) -> Box<dyn Loss> {
+        Box::new(self.clone())
+    }
+}
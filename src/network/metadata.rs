@@ -0,0 +1,59 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Information about the training of a model, saved with it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    /// Name of the training set
+    pub dataset: String,
+    /// Number of batches learned
+    pub batches: u64,
+    /// Accuracy measured after the training, between 0 and 1
+    pub accuracy: f64,
+    /// End of the last training, in seconds since the Unix epoch (0 if unknown)
+    pub date: u64,
+}
+
+impl Metadata {
+    /// Seconds since the Unix epoch
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    }
+
+    /// The date as `YYYY-MM-DD HH:MM:SS UTC`
+    pub fn formatted_date(&self) -> String {
+        if self.date == 0 {
+            return String::from("unknown");
+        }
+
+        let days = (self.date / 86400) as i64;
+        let seconds = self.date % 86400;
+
+        // civil date from the number of days since 1970-01-01 (proleptic Gregorian calendar)
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
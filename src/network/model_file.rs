@@ -0,0 +1,467 @@
+//! Binary format of the saved models.
+//!
+//! Version 2, every number being big-endian:
+//! - magic `ANRM`, format version (u16)
+//! - metadata: dataset (u16 length + UTF-8), batches learned (u64), accuracy (f64), date (u64)
+//! - normalization of the inputs (u8: 0 none, 1 unit, 2 symmetric, 3 standardize), followed
+//!   for the standardization by the input count (u32), the means and the standard deviations (f64)
+//! - layer count (u16), then for each layer: type (u8, 0 for dense), size_in (u32),
+//!   size_out (u32), activation name (u8 length + UTF-8) and parameter (f64),
+//!   weights (size_out x size_in f64, row by row) and biases (size_out f64)
+//! - CRC32 (u32) of all the previous bytes
+//!
+//! Version 1 is the same without the normalization, the inputs being used as they are.
+//!
+//! The legacy format has no header: layer count + 1 (u16), the sizes of all the layers (u16),
+//! then the weights and biases of each layer, optionally followed by the activation of each layer.
+
+use crate::{error::Error, matrix::matrix::Matrix};
+
+use super::{
+    activations::{self, Activation},
+    layer::Layer,
+    metadata::Metadata,
+    normalization::Normalization,
+};
+
+pub const MAGIC: &[u8; 4] = b"ANRM";
+pub const FORMAT_VERSION: u16 = 2;
+const LAYER_DENSE: u8 = 0;
+
+const NORMALIZATION_NONE: u8 = 0;
+const NORMALIZATION_UNIT: u8 = 1;
+const NORMALIZATION_SYMMETRIC: u8 = 2;
+const NORMALIZATION_STANDARDIZE: u8 = 3;
+
+/// Write the layers, the metadata and the normalization in the current format
+pub fn encode(layers: &[Layer], metadata: &Metadata, normalization: &Normalization) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+
+    write_string(&mut bytes, &metadata.dataset, 2);
+    bytes.extend_from_slice(&metadata.batches.to_be_bytes());
+    bytes.extend_from_slice(&metadata.accuracy.to_be_bytes());
+    bytes.extend_from_slice(&metadata.date.to_be_bytes());
+
+    match normalization {
+        Normalization::None => bytes.push(NORMALIZATION_NONE),
+        Normalization::Unit => bytes.push(NORMALIZATION_UNIT),
+        Normalization::Symmetric => bytes.push(NORMALIZATION_SYMMETRIC),
+        Normalization::Standardize { mean, std } => {
+            bytes.push(NORMALIZATION_STANDARDIZE);
+            bytes.extend_from_slice(&(mean.len() as u32).to_be_bytes());
+            for value in mean.iter().chain(std) {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+
+    bytes.extend_from_slice(&(layers.len() as u16).to_be_bytes());
+    for layer in layers {
+        bytes.push(LAYER_DENSE);
+        bytes.extend_from_slice(&(layer.size_in as u32).to_be_bytes());
+        bytes.extend_from_slice(&(layer.size_out as u32).to_be_bytes());
+        write_string(&mut bytes, layer.activation().name(), 1);
+        bytes.extend_from_slice(&layer.activation().parameter().to_be_bytes());
+        for value in layer.weights.data.iter().chain(&layer.biases.data) {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    let checksum = crc32fast::hash(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+
+    bytes
+}
+
+/// Read a model in the current, in an older or in the legacy format
+pub fn decode(bytes: &[u8]) -> Result<(Vec<Layer>, Metadata, Normalization), Error> {
+    if bytes.starts_with(MAGIC) {
+        decode_current(bytes)
+    } else {
+        Ok((
+            decode_legacy(bytes)?,
+            Metadata::default(),
+            Normalization::None,
+        ))
+    }
+}
+
+fn decode_current(bytes: &[u8]) -> Result<(Vec<Layer>, Metadata, Normalization), Error> {
+    let mut reader = Reader::new(bytes);
+    reader.take(MAGIC.len(), "magic")?;
+    let version = reader.u16("version")?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    if bytes.len() < reader.position + 4 {
+        return Err(Error::Truncated("checksum"));
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    let expected = u32::from_be_bytes(checksum.try_into().unwrap());
+    let found = crc32fast::hash(content);
+
+    reader.bytes = content;
+    match decode_content(&mut reader, version) {
+        Ok(model) if expected == found => Ok(model),
+        // a truncated file can't have a valid checksum, the missing value is more helpful
+        Err(Error::Truncated(what)) => Err(Error::Truncated(what)),
+        Err(error) if expected == found => Err(error),
+        _ => Err(Error::ChecksumMismatch { expected, found }),
+    }
+}
+
+/// Metadata, normalization and layers, between the version and the checksum
+fn decode_content(
+    reader: &mut Reader,
+    version: u16,
+) -> Result<(Vec<Layer>, Metadata, Normalization), Error> {
+    let metadata = Metadata {
+        dataset: reader.string(2, "dataset")?,
+        batches: reader.u64("batch count")?,
+        accuracy: reader.f64("accuracy")?,
+        date: reader.u64("date")?,
+    };
+    let normalization = if version >= 2 {
+        read_normalization(reader)?
+    } else {
+        Normalization::None
+    };
+
+    let layer_count = reader.u16("layer count")? as usize;
+    if layer_count == 0 {
+        return Err(Error::NoLayers);
+    }
+    let mut layers: Vec<Layer> = Vec::new();
+    for i in 0..layer_count {
+        let layer_type = reader.u8("layer type")?;
+        if layer_type != LAYER_DENSE {
+            return Err(Error::UnknownLayerType(layer_type));
+        }
+
+        let size_in = reader.u32("layer size")? as usize;
+        let size_out = reader.u32("layer size")? as usize;
+        if let Some(previous) = layers.last() {
+            if previous.size_out != size_in {
+                return Err(Error::ShapeMismatch {
+                    layer: i,
+                    expected: previous.size_out,
+                    found: size_in,
+                });
+            }
+        }
+        let activation = read_activation(reader)?;
+        let weights = reader.matrix(size_out, size_in, "weights")?;
+        let biases = reader.matrix(size_out, 1, "biases")?;
+
+        let mut layer = Layer::new(size_in, size_out, activation);
+        layer.weights = weights;
+        layer.biases = biases;
+        layers.push(layer);
+    }
+    if !reader.is_empty() {
+        return Err(Error::TrailingData(reader.remaining()));
+    }
+    normalization.check_input_size(layers[0].size_in)?;
+
+    Ok((layers, metadata, normalization))
+}
+
+fn decode_legacy(bytes: &[u8]) -> Result<Vec<Layer>, Error> {
+    let mut reader = Reader::new(bytes);
+
+    let size_count = reader.u16("layer count")? as usize;
+    let sizes = (0..size_count)
+        .map(|_| Ok(reader.u16("layer size")? as usize))
+        .collect::<Result<Vec<usize>, Error>>()?;
+    // without any magic, a header that can't describe a network means it isn't a model
+    if size_count < 2 || sizes.contains(&0) {
+        return Err(Error::BadMagic);
+    }
+
+    let mut layers = Vec::new();
+    for i in 0..size_count - 1 {
+        let weights = reader.matrix(sizes[i + 1], sizes[i], "weights")?;
+        let biases = reader.matrix(sizes[i + 1], 1, "biases")?;
+
+        // only the sigmoid was available before the activations were saved
+        let mut layer = Layer::new(sizes[i], sizes[i + 1], Activation::Sigmoid);
+        layer.weights = weights;
+        layer.biases = biases;
+        layers.push(layer);
+    }
+
+    // activation of each layer, absent from the oldest models
+    if !reader.is_empty() {
+        for layer in layers.iter_mut() {
+            layer.set_activation(read_activation(&mut reader)?);
+        }
+    }
+    if !reader.is_empty() {
+        return Err(Error::TrailingData(reader.remaining()));
+    }
+
+    Ok(layers)
+}
+
+fn read_activation(reader: &mut Reader) -> Result<Activation, Error> {
+    let name = reader.string(1, "activation")?;
+    let parameter = reader.f64("activation parameter")?;
+
+    activations::from_name(&name, Some(parameter)).ok_or(Error::UnknownActivation(name))
+}
+
+fn read_normalization(reader: &mut Reader) -> Result<Normalization, Error> {
+    match reader.u8("normalization")? {
+        NORMALIZATION_NONE => Ok(Normalization::None),
+        NORMALIZATION_UNIT => Ok(Normalization::Unit),
+        NORMALIZATION_SYMMETRIC => Ok(Normalization::Symmetric),
+        NORMALIZATION_STANDARDIZE => {
+            let count = reader.u32("normalization size")? as usize;
+            Ok(Normalization::Standardize {
+                mean: reader.matrix(count, 1, "normalization means")?.data,
+                std: reader.matrix(count, 1, "normalization deviations")?.data,
+            })
+        }
+        kind => Err(Error::UnknownNormalization(kind.to_string())),
+    }
+}
+
+/// UTF-8 string preceded by its length on `length_size` bytes, cut if it is too long
+fn write_string(bytes: &mut Vec<u8>, string: &str, length_size: usize) {
+    let mut length = string.len().min((1 << (8 * length_size)) - 1);
+    while !string.is_char_boundary(length) {
+        length -= 1;
+    }
+    bytes.extend_from_slice(&(length as u64).to_be_bytes()[8 - length_size..]);
+    bytes.extend_from_slice(&string.as_bytes()[..length]);
+}
+
+/// Reads big-endian values one after the other
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, position: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// `what` names the value in the error if the bytes end before it
+    fn take(&mut self, count: usize, what: &'static str) -> Result<&'a [u8], Error> {
+        if count > self.remaining() {
+            return Err(Error::Truncated(what));
+        }
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self, what: &'static str) -> Result<u8, Error> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    fn u16(&mut self, what: &'static str) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.take(2, what)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self, what: &'static str) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4, what)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self, what: &'static str) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.take(8, what)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self, what: &'static str) -> Result<f64, Error> {
+        Ok(f64::from_be_bytes(self.take(8, what)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self, length_size: usize, what: &'static str) -> Result<String, Error> {
+        let mut length_bytes = [0; 8];
+        length_bytes[8 - length_size..].copy_from_slice(self.take(length_size, what)?);
+        let length = u64::from_be_bytes(length_bytes) as usize;
+
+        Ok(String::from_utf8_lossy(self.take(length, what)?).into_owned())
+    }
+
+    fn matrix(&mut self, rows: usize, cols: usize, what: &'static str) -> Result<Matrix, Error> {
+        // checked before allocating, the sizes of a corrupted file can be huge
+        let bytes = self.take(rows.saturating_mul(cols).saturating_mul(8), what)?;
+        let data = bytes
+            .chunks_exact(8)
+            .map(|value| f64::from_be_bytes(value.try_into().unwrap()))
+            .collect();
+
+        Ok(Matrix::from_vec(rows, cols, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::network::Network;
+
+    /// A small model saved in memory
+    fn saved() -> Vec<u8> {
+        let mut network = Network::new_seeded(
+            &[3, 4, 2],
+            &1.0,
+            vec![Activation::LeakyRelu(0.2), Activation::Sigmoid],
+            7,
+        );
+        network.set_normalization(Normalization::Standardize {
+            mean: vec![0.5, 1.0, 1.5],
+            std: vec![2.0, 2.5, 3.0],
+        });
+
+        let mut bytes = Vec::new();
+        network.save(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn load(bytes: &[u8]) -> Result<Network, Error> {
+        Network::load(&mut &bytes[..], &1.0)
+    }
+
+    #[test]
+    fn loads_a_model_saved_in_memory() {
+        let bytes = saved();
+        assert!(bytes.starts_with(MAGIC));
+
+        let network = load(&bytes).unwrap();
+        let mut reloaded = Vec::new();
+        network.save(&mut reloaded).unwrap();
+        assert_eq!(reloaded, bytes);
+        assert_eq!(
+            *network.normalization(),
+            Normalization::Standardize {
+                mean: vec![0.5, 1.0, 1.5],
+                std: vec![2.0, 2.5, 3.0],
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_a_bad_magic() {
+        let mut bytes = saved();
+        // read as a legacy header of 3 layers, the first one being empty
+        bytes[..4].copy_from_slice(&[0, 3, 0, 0]);
+        assert!(matches!(load(&bytes).err(), Some(Error::BadMagic)));
+    }
+
+    #[test]
+    fn rejects_a_newer_version() {
+        let mut bytes = saved();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        assert!(matches!(
+            load(&bytes).err(),
+            Some(Error::UnsupportedVersion(version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_a_truncated_model() {
+        let bytes = saved();
+        assert!(matches!(
+            load(&bytes[..8]).err(),
+            Some(Error::Truncated("checksum"))
+        ));
+        assert!(matches!(
+            load(&bytes[..bytes.len() / 2]).err(),
+            Some(Error::Truncated(_))
+        ));
+        assert!(matches!(
+            load(&bytes[..bytes.len() - 1]).err(),
+            Some(Error::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_a_corrupted_model() {
+        let mut bytes = saved();
+        // last byte of the last bias, before the checksum
+        let index = bytes.len() - 5;
+        bytes[index] ^= 1;
+        assert!(matches!(
+            load(&bytes).err(),
+            Some(Error::ChecksumMismatch { expected, found }) if expected != found
+        ));
+    }
+
+    #[test]
+    fn rejects_a_model_without_layers() {
+        let bytes = encode(&[], &Metadata::default(), &Normalization::None);
+        assert!(matches!(load(&bytes).err(), Some(Error::NoLayers)));
+    }
+
+    /// Model of the legacy format with the sizes 2, 3 and 1, every parameter being its index
+    /// divided by 4, optionally followed by the activations
+    fn legacy(activations: &[(&str, f64)]) -> Vec<u8> {
+        let sizes: [u16; 3] = [2, 3, 1];
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(sizes.len() as u16).to_be_bytes());
+        for size in sizes {
+            bytes.extend_from_slice(&size.to_be_bytes());
+        }
+        // weights (3x2) and biases (3) of the hidden layer, then the ones of the output layer
+        for i in 0..(6 + 3 + 3 + 1) {
+            bytes.extend_from_slice(&(i as f64 / 4.0).to_be_bytes());
+        }
+        for (name, parameter) in activations {
+            write_string(&mut bytes, name, 1);
+            bytes.extend_from_slice(&parameter.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn loads_the_legacy_format() {
+        let (layers, metadata, normalization) = decode(&legacy(&[])).unwrap();
+
+        assert_eq!(metadata, Metadata::default());
+        assert_eq!(normalization, Normalization::None);
+        assert_eq!(layers.len(), 2);
+        assert_eq!((layers[0].size_in, layers[0].size_out), (2, 3));
+        assert_eq!((layers[1].size_in, layers[1].size_out), (3, 1));
+        assert_eq!(
+            layers[0].weights.data,
+            vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.25]
+        );
+        assert_eq!(layers[0].biases.data, vec![1.5, 1.75, 2.0]);
+        assert_eq!(layers[1].weights.data, vec![2.25, 2.5, 2.75]);
+        assert_eq!(layers[1].biases.data, vec![3.0]);
+        // only the sigmoid existed before the activations were saved
+        for layer in &layers {
+            assert_eq!(*layer.activation(), Activation::Sigmoid);
+        }
+    }
+
+    #[test]
+    fn loads_the_legacy_activations() {
+        let bytes = legacy(&[("leaky_relu", 0.1), ("tanh", 0.0)]);
+        let (layers, _, _) = decode(&bytes).unwrap();
+        assert_eq!(*layers[0].activation(), Activation::LeakyRelu(0.1));
+        assert_eq!(*layers[1].activation(), Activation::Tanh);
+
+        let mut bytes = bytes;
+        bytes.push(0);
+        assert!(matches!(decode(&bytes).err(), Some(Error::TrailingData(1))));
+        assert!(matches!(
+            decode(&legacy(&[])[..20]).err(),
+            Some(Error::Truncated("weights"))
+        ));
+    }
+}
@@ -0,0 +1,304 @@
+//! JSON representation of the models, for inspection and use outside of this crate:
+//!
+//! ```json
+//! {
+//!   "format": "ai-number-recognition",
+//!   "version": 2,
+//!   "metadata": {"dataset": "train-images", "batches": 10000, "accuracy": 0.97, "date": 1700000000},
+//!   "normalization": {"type": "standardize", "mean": [...], "std": [...]},
+//!   "layers": [
+//!     {
+//!       "type": "dense",
+//!       "size_in": 784,
+//!       "size_out": 100,
+//!       "activation": "leaky_relu:0.01",
+//!       "weights": [[...], ...],
+//!       "biases": [...]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `weights` has `size_out` rows of `size_in` values. The normalization type is `none`, `unit`,
+//! `symmetric` or `standardize`, only the last one having the `mean` and `std` of each input.
+//! Version 1 has no normalization. The numbers are written so they read back to exactly the
+//! same `f64`, the sizes, `batches` and `date` being exact integers. JSON having no non-finite
+//! numbers, the models holding any can't be exported.
+
+use crate::{
+    error::Error,
+    json::{self, Value},
+    matrix::matrix::Matrix,
+};
+
+use super::{
+    activations::Activation,
+    layer::Layer,
+    metadata::Metadata,
+    normalization::{self, Normalization},
+};
+
+const FORMAT_NAME: &str = "ai-number-recognition";
+const FORMAT_VERSION: u64 = 2;
+
+pub fn encode(
+    layers: &[Layer],
+    metadata: &Metadata,
+    normalization: &Normalization,
+    pretty: bool,
+) -> Result<String, Error> {
+    let number = |value: f64| Value::Number(value);
+    let integer = |value: usize| Value::Integer(value as u64);
+
+    for (i, layer) in layers.iter().enumerate() {
+        check_finite(&layer.weights.data, &format!("weights of layer {}", i))?;
+        check_finite(&layer.biases.data, &format!("biases of layer {}", i))?;
+    }
+    if let Normalization::Standardize { mean, std } = normalization {
+        check_finite(mean, "normalization mean")?;
+        check_finite(std, "normalization std")?;
+    }
+    check_finite(&[metadata.accuracy], "accuracy")?;
+
+    let layers = layers
+        .iter()
+        .map(|layer| {
+            let weights = (0..layer.size_out)
+                .map(|row| {
+                    Value::Array(layer.weights.row(row).iter().copied().map(number).collect())
+                })
+                .collect();
+
+            Value::Object(vec![
+                ("type".to_string(), Value::String("dense".to_string())),
+                ("size_in".to_string(), integer(layer.size_in)),
+                ("size_out".to_string(), integer(layer.size_out)),
+                (
+                    "activation".to_string(),
+                    Value::String(layer.activation().to_string()),
+                ),
+                ("weights".to_string(), Value::Array(weights)),
+                (
+                    "biases".to_string(),
+                    Value::Array(layer.biases.data.iter().copied().map(number).collect()),
+                ),
+            ])
+        })
+        .collect();
+
+    let mut normalization_members = vec![(
+        "type".to_string(),
+        Value::String(normalization.name().to_string()),
+    )];
+    if let Normalization::Standardize { mean, std } = normalization {
+        normalization_members.push((
+            "mean".to_string(),
+            Value::Array(mean.iter().copied().map(number).collect()),
+        ));
+        normalization_members.push((
+            "std".to_string(),
+            Value::Array(std.iter().copied().map(number).collect()),
+        ));
+    }
+
+    let model = Value::Object(vec![
+        ("format".to_string(), Value::String(FORMAT_NAME.to_string())),
+        ("version".to_string(), Value::Integer(FORMAT_VERSION)),
+        (
+            "metadata".to_string(),
+            Value::Object(vec![
+                (
+                    "dataset".to_string(),
+                    Value::String(metadata.dataset.clone()),
+                ),
+                ("batches".to_string(), Value::Integer(metadata.batches)),
+                ("accuracy".to_string(), number(metadata.accuracy)),
+                ("date".to_string(), Value::Integer(metadata.date)),
+            ]),
+        ),
+        (
+            "normalization".to_string(),
+            Value::Object(normalization_members),
+        ),
+        ("layers".to_string(), Value::Array(layers)),
+    ]);
+
+    Ok(if pretty {
+        model.to_pretty()
+    } else {
+        model.to_compact()
+    })
+}
+
+fn check_finite(values: &[f64], name: &str) -> Result<(), Error> {
+    match values.iter().find(|value| !value.is_finite()) {
+        Some(value) => Err(Error::NotExportable(format!(
+            "the {} hold {}, which JSON can't represent",
+            name, value
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub fn decode(text: &str) -> Result<(Vec<Layer>, Metadata, Normalization), Error> {
+    let model = json::parse(text).map_err(Error::InvalidJson)?;
+
+    if member(&model, "format", "model")?.as_str() != Some(FORMAT_NAME) {
+        return Err(Error::BadMagic);
+    }
+    let version = integer(&model, "version", "model")?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(
+            version.min(u16::MAX as u64) as u16
+        ));
+    }
+
+    let metadata_value = member(&model, "metadata", "model")?;
+    let metadata = Metadata {
+        dataset: string(metadata_value, "dataset", "metadata")?.to_string(),
+        batches: match metadata_value.get("batches") {
+            Some(_) => integer(metadata_value, "batches", "metadata")?,
+            // the first JSON models named the number of batches `epochs`
+            None => integer(metadata_value, "epochs", "metadata")?,
+        },
+        accuracy: number(metadata_value, "accuracy", "metadata")?,
+        date: integer(metadata_value, "date", "metadata")?,
+    };
+
+    let normalization = if version >= 2 {
+        decode_normalization(member(&model, "normalization", "model")?)?
+    } else {
+        Normalization::None
+    };
+
+    let layer_values = array(&model, "layers", "model")?;
+    if layer_values.is_empty() {
+        return Err(invalid("model", "layers", "a non-empty array"));
+    }
+    let mut layers: Vec<Layer> = Vec::new();
+    for (i, layer_value) in layer_values.iter().enumerate() {
+        let context = format!("layer {}", i);
+        let layer_type = string(layer_value, "type", &context)?;
+        if layer_type != "dense" {
+            return Err(Error::InvalidJson(format!(
+                "unknown layer type: {}",
+                layer_type
+            )));
+        }
+
+        let size_in = integer(layer_value, "size_in", &context)? as usize;
+        let size_out = integer(layer_value, "size_out", &context)? as usize;
+        if let Some(previous) = layers.last() {
+            if previous.size_out != size_in {
+                return Err(Error::ShapeMismatch {
+                    layer: i,
+                    expected: previous.size_out,
+                    found: size_in,
+                });
+            }
+        }
+
+        let activation_name = string(layer_value, "activation", &context)?;
+        let activation: Activation = activation_name
+            .parse()
+            .map_err(|_| Error::UnknownActivation(activation_name.to_string()))?;
+
+        // the sizes are checked against the arrays before allocating anything from them
+        let rows = array(layer_value, "weights", &context)?;
+        if rows.len() != size_out {
+            return Err(invalid(&context, "weights", "size_out rows"));
+        }
+        let mut weights = Vec::new();
+        for row in rows {
+            let row = row
+                .as_array()
+                .ok_or_else(|| invalid(&context, "weights", "an array of rows"))?;
+            if row.len() != size_in {
+                return Err(invalid(&context, "weights", "rows of size_in numbers"));
+            }
+            weights.extend(numbers(row, &context, "weights")?);
+        }
+
+        let biases = numbers(array(layer_value, "biases", &context)?, &context, "biases")?;
+        if biases.len() != size_out {
+            return Err(invalid(&context, "biases", "size_out numbers"));
+        }
+
+        let mut layer = Layer::new(size_in, size_out, activation);
+        layer.weights = Matrix::from_vec(size_out, size_in, weights);
+        layer.biases = Matrix::from_vec(size_out, 1, biases);
+        layers.push(layer);
+    }
+
+    normalization.check_input_size(layers[0].size_in)?;
+
+    Ok((layers, metadata, normalization))
+}
+
+fn decode_normalization(value: &Value) -> Result<Normalization, Error> {
+    let name = string(value, "type", "normalization")?;
+    if name != "standardize" {
+        return normalization::from_name(name, &[])
+            .ok_or_else(|| Error::UnknownNormalization(name.to_string()));
+    }
+
+    let mean = numbers(
+        array(value, "mean", "normalization")?,
+        "normalization",
+        "mean",
+    )?;
+    let std = numbers(
+        array(value, "std", "normalization")?,
+        "normalization",
+        "std",
+    )?;
+    if std.len() != mean.len() {
+        return Err(invalid("normalization", "std", "as long as the mean"));
+    }
+    Ok(Normalization::Standardize { mean, std })
+}
+
+fn invalid(context: &str, name: &str, expected: &str) -> Error {
+    Error::InvalidJson(format!("{} of the {} must be {}", name, context, expected))
+}
+
+fn member<'a>(object: &'a Value, name: &str, context: &str) -> Result<&'a Value, Error> {
+    object
+        .get(name)
+        .ok_or_else(|| Error::InvalidJson(format!("missing {} of the {}", name, context)))
+}
+
+fn number(object: &Value, name: &str, context: &str) -> Result<f64, Error> {
+    member(object, name, context)?
+        .as_f64()
+        .ok_or_else(|| invalid(context, name, "a number"))
+}
+
+fn integer(object: &Value, name: &str, context: &str) -> Result<u64, Error> {
+    member(object, name, context)?
+        .as_u64()
+        .ok_or_else(|| invalid(context, name, "a positive integer"))
+}
+
+fn string<'a>(object: &'a Value, name: &str, context: &str) -> Result<&'a str, Error> {
+    member(object, name, context)?
+        .as_str()
+        .ok_or_else(|| invalid(context, name, "a string"))
+}
+
+fn array<'a>(object: &'a Value, name: &str, context: &str) -> Result<&'a [Value], Error> {
+    member(object, name, context)?
+        .as_array()
+        .ok_or_else(|| invalid(context, name, "an array"))
+}
+
+fn numbers(values: &[Value], context: &str, name: &str) -> Result<Vec<f64>, Error> {
+    values
+        .iter()
+        .map(|value| {
+            value
+                .as_f64()
+                .ok_or_else(|| invalid(context, name, "numbers"))
+        })
+        .collect()
+}
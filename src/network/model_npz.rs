@@ -0,0 +1,195 @@
+//! Parameters of the models as NumPy arrays, in a `.npz` archive:
+//! - `layer{i}_weights`: `size_out` x `size_in` array of the layer `i`, from the input layer
+//! - `layer{i}_biases`: `size_out` array (`size_out` x 1 and 1 x `size_out` are also read)
+//! - `activations`: the activation of each layer, as `name` or `name:parameter` strings
+//! - `normalization`: the name of the transform of the inputs, in a single string
+//! - `normalization_mean`, `normalization_std`: `size_in` arrays of the standardization
+//!
+//! In NumPy, the output of a layer is `activation(weights @ x + biases)`. The activations are
+//! optional when reading, the layers without one use the sigmoid, and so is the normalization,
+//! the inputs being used as they are without it.
+
+use crate::{
+    error::Error,
+    matrix::matrix::Matrix,
+    numpy::{self, Array},
+};
+
+use super::{
+    activations::Activation,
+    layer::Layer,
+    normalization::{self, Normalization},
+};
+
+const ACTIVATIONS: &str = "activations";
+const NORMALIZATION: &str = "normalization";
+const NORMALIZATION_MEAN: &str = "normalization_mean";
+const NORMALIZATION_STD: &str = "normalization_std";
+
+pub fn encode(layers: &[Layer], normalization: &Normalization) -> Vec<u8> {
+    let mut arrays = Vec::new();
+    for (i, layer) in layers.iter().enumerate() {
+        arrays.push((
+            format!("layer{}_weights", i),
+            Array::numbers(
+                vec![layer.size_out, layer.size_in],
+                layer.weights.data.clone(),
+            ),
+        ));
+        arrays.push((
+            format!("layer{}_biases", i),
+            Array::numbers(vec![layer.size_out], layer.biases.data.clone()),
+        ));
+    }
+    arrays.push((
+        ACTIVATIONS.to_string(),
+        Array::strings(
+            layers
+                .iter()
+                .map(|layer| layer.activation().to_string())
+                .collect(),
+        ),
+    ));
+    arrays.push((
+        NORMALIZATION.to_string(),
+        Array::strings(vec![normalization.name().to_string()]),
+    ));
+    if let Normalization::Standardize { mean, std } = normalization {
+        arrays.push((
+            NORMALIZATION_MEAN.to_string(),
+            Array::numbers(vec![mean.len()], mean.clone()),
+        ));
+        arrays.push((
+            NORMALIZATION_STD.to_string(),
+            Array::numbers(vec![std.len()], std.clone()),
+        ));
+    }
+
+    numpy::write_npz(&arrays)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<(Vec<Layer>, Normalization), Error> {
+    let arrays = numpy::read_npz(bytes).map_err(Error::InvalidNumpy)?;
+    let find = |name: &str| {
+        arrays
+            .iter()
+            .find(|(array_name, _)| array_name == name)
+            .map(|(_, array)| array)
+    };
+
+    let activations = match find(ACTIVATIONS) {
+        Some(array) => array
+            .as_strings()
+            .ok_or_else(|| Error::InvalidNumpy(format!("{} must be strings", ACTIVATIONS)))?
+            .iter()
+            .map(|name| {
+                name.parse()
+                    .map_err(|_| Error::UnknownActivation(name.clone()))
+            })
+            .collect::<Result<Vec<Activation>, Error>>()?,
+        None => Vec::new(),
+    };
+
+    let mut layers: Vec<Layer> = Vec::new();
+    while let Some(weights) = find(&format!("layer{}_weights", layers.len())) {
+        let i = layers.len();
+        let (size_out, size_in) = match weights.shape.as_slice() {
+            &[size_out, size_in] => (size_out, size_in),
+            shape => {
+                return Err(Error::InvalidNumpy(format!(
+                    "layer{}_weights must have 2 dimensions, not {}",
+                    i,
+                    shape.len()
+                )))
+            }
+        };
+        if let Some(previous) = layers.last() {
+            if previous.size_out != size_in {
+                return Err(Error::ShapeMismatch {
+                    layer: i,
+                    expected: previous.size_out,
+                    found: size_in,
+                });
+            }
+        }
+
+        let biases = find(&format!("layer{}_biases", i))
+            .ok_or_else(|| Error::InvalidNumpy(format!("missing layer{}_biases", i)))?;
+        if biases.shape.iter().product::<usize>() != size_out
+            || biases.shape.iter().filter(|&&size| size != 1).count() > 1
+        {
+            return Err(Error::InvalidNumpy(format!(
+                "layer{}_biases must have {} elements, one per row of the weights",
+                i, size_out
+            )));
+        }
+
+        let numbers = |array: &Array, name: &str| {
+            array
+                .as_numbers()
+                .map(<[f64]>::to_vec)
+                .ok_or_else(|| Error::InvalidNumpy(format!("layer{}_{} must be numbers", i, name)))
+        };
+        let activation = activations.get(i).cloned().unwrap_or(Activation::Sigmoid);
+
+        let mut layer = Layer::new(size_in, size_out, activation);
+        layer.weights = Matrix::from_vec(size_out, size_in, numbers(weights, "weights")?);
+        layer.biases = Matrix::from_vec(size_out, 1, numbers(biases, "biases")?);
+        layers.push(layer);
+    }
+
+    if layers.is_empty() {
+        return Err(Error::InvalidNumpy("missing layer0_weights".to_string()));
+    }
+    if activations.len() > layers.len() {
+        return Err(Error::InvalidNumpy(format!(
+            "{} activations for {} layers",
+            activations.len(),
+            layers.len()
+        )));
+    }
+
+    let normalization = match find(NORMALIZATION) {
+        Some(array) => decode_normalization(array, &find)?,
+        None => Normalization::None,
+    };
+    normalization.check_input_size(layers[0].size_in)?;
+
+    Ok((layers, normalization))
+}
+
+fn decode_normalization<'a>(
+    array: &Array,
+    find: &impl Fn(&str) -> Option<&'a Array>,
+) -> Result<Normalization, Error> {
+    let name = match array.as_strings() {
+        Some([name]) => name,
+        _ => {
+            return Err(Error::InvalidNumpy(format!(
+                "{} must be a single string",
+                NORMALIZATION
+            )))
+        }
+    };
+    if name != "standardize" {
+        return normalization::from_name(name, &[])
+            .ok_or_else(|| Error::UnknownNormalization(name.clone()));
+    }
+
+    let numbers = |name: &str| {
+        find(name)
+            .ok_or_else(|| Error::InvalidNumpy(format!("missing {}", name)))?
+            .as_numbers()
+            .map(<[f64]>::to_vec)
+            .ok_or_else(|| Error::InvalidNumpy(format!("{} must be numbers", name)))
+    };
+    let mean = numbers(NORMALIZATION_MEAN)?;
+    let std = numbers(NORMALIZATION_STD)?;
+    if std.len() != mean.len() {
+        return Err(Error::InvalidNumpy(format!(
+            "{} must have as many elements as {}",
+            NORMALIZATION_STD, NORMALIZATION_MEAN
+        )));
+    }
+    Ok(Normalization::Standardize { mean, std })
+}
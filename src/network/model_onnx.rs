@@ -0,0 +1,864 @@
+//! ONNX export of the models, for the runtimes consuming ONNX (opset 13).
+//!
+//! The graph takes a `batch` x `size_in` float tensor named `input` and gives the `output` of the
+//! last layer. The normalization of the model comes first, as arithmetic nodes dividing by 255 or
+//! subtracting the `normalization_mean` and dividing by the `normalization_std`. Each layer is a
+//! `Gemm` node computing `input · weightsᵀ + biases` from the `layer{i}_weights` and
+//! `layer{i}_biases` initializers, followed by the nodes of its activation (GELU and swish being
+//! made of several nodes). The weights are rounded to `f32`, the type of the tensors supported by
+//! every runtime.
+//!
+//! The graphs can also be read back and evaluated, to check an exported model against the network.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::Error,
+    matrix::matrix::Matrix,
+    protobuf::{self, Message, Value},
+};
+
+use super::{
+    activations::{Activation, SELU_ALPHA, SELU_SCALE},
+    layer::Layer,
+    metadata::Metadata,
+    normalization::{Normalization, PIXEL_MAX},
+};
+
+const IR_VERSION: i64 = 7;
+const OPSET_VERSION: i64 = 13;
+/// `TensorProto.DataType` of the `f32` tensors
+const FLOAT: i64 = 1;
+/// `AttributeProto.AttributeType` values
+const ATTRIBUTE_FLOAT: i64 = 1;
+const ATTRIBUTE_INT: i64 = 2;
+
+/// Largest difference expected between the outputs of a network and of its exported graph, the
+/// weights being rounded to `f32`
+pub const TOLERANCE: f64 = 1e-4;
+
+pub fn encode(
+    layers: &[Layer],
+    metadata: &Metadata,
+    normalization: &Normalization,
+) -> Result<Vec<u8>, Error> {
+    let mut graph = GraphBuilder::default();
+
+    let mut input = graph.normalization(normalization, "input");
+    for (i, layer) in layers.iter().enumerate() {
+        let weights = format!("layer{}_weights", i);
+        let biases = format!("layer{}_biases", i);
+        graph.initializer(
+            &weights,
+            &[layer.size_out, layer.size_in],
+            &layer.weights.data,
+        );
+        graph.initializer(&biases, &[layer.size_out], &layer.biases.data);
+
+        let weighted_output = format!("layer{}_weighted_output", i);
+        let output = if i + 1 == layers.len() {
+            "output".to_string()
+        } else {
+            format!("layer{}_output", i)
+        };
+        graph.node(
+            "Gemm",
+            &[&input, &weights, &biases],
+            &weighted_output,
+            &[("transB", Attribute::Int(1))],
+        );
+        graph.activation(layer.activation(), &weighted_output, &output)?;
+        input = output;
+    }
+
+    let mut graph_message = Message::new();
+    for node in &graph.nodes {
+        graph_message.message(1, node);
+    }
+    graph_message.string(2, "network");
+    for initializer in &graph.initializers {
+        graph_message.message(5, initializer);
+    }
+    graph_message.message(11, &value_info("input", layers[0].size_in));
+    graph_message.message(12, &value_info("output", layers[layers.len() - 1].size_out));
+
+    let mut model = Message::new();
+    model
+        .int(1, IR_VERSION)
+        .string(2, env!("CARGO_PKG_NAME"))
+        .string(3, env!("CARGO_PKG_VERSION"))
+        .message(7, &graph_message)
+        .message(8, Message::new().string(1, "").int(2, OPSET_VERSION));
+    for (key, value) in [
+        ("dataset", metadata.dataset.clone()),
+        ("batches", metadata.batches.to_string()),
+        ("accuracy", metadata.accuracy.to_string()),
+        ("date", metadata.formatted_date()),
+    ] {
+        model.message(14, Message::new().string(1, key).string(2, &value));
+    }
+
+    Ok(model.into_bytes())
+}
+
+/// `ValueInfoProto` of a `batch` x `size` float tensor
+fn value_info(name: &str, size: usize) -> Message {
+    let mut shape = Message::new();
+    shape
+        .message(1, Message::new().string(2, "batch"))
+        .message(1, Message::new().int(1, size as i64));
+    let mut tensor_type = Message::new();
+    tensor_type.int(1, FLOAT).message(2, &shape);
+
+    let mut info = Message::new();
+    info.string(1, name)
+        .message(2, Message::new().message(1, &tensor_type));
+    info
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Attribute {
+    Float(f64),
+    Int(i64),
+}
+
+#[derive(Default)]
+struct GraphBuilder {
+    nodes: Vec<Message>,
+    initializers: Vec<Message>,
+    /// Names of the scalar initializers already added
+    constants: Vec<String>,
+}
+
+impl GraphBuilder {
+    fn node(
+        &mut self,
+        op_type: &str,
+        inputs: &[&str],
+        output: &str,
+        attributes: &[(&str, Attribute)],
+    ) {
+        let mut node = Message::new();
+        for input in inputs {
+            node.string(1, input);
+        }
+        node.string(2, output)
+            .string(3, &format!("{}_{}", output, op_type))
+            .string(4, op_type);
+        for (name, value) in attributes {
+            let mut attribute = Message::new();
+            attribute.string(1, name);
+            match *value {
+                Attribute::Float(value) => {
+                    attribute.float(2, value as f32).int(20, ATTRIBUTE_FLOAT)
+                }
+                Attribute::Int(value) => attribute.int(3, value).int(20, ATTRIBUTE_INT),
+            };
+            node.message(5, &attribute);
+        }
+        self.nodes.push(node);
+    }
+
+    fn initializer(&mut self, name: &str, dims: &[usize], values: &[f64]) {
+        let mut tensor = Message::new();
+        for &dim in dims {
+            tensor.int(1, dim as i64);
+        }
+        let raw_data: Vec<u8> = values
+            .iter()
+            .flat_map(|&value| (value as f32).to_le_bytes())
+            .collect();
+        tensor.int(2, FLOAT).string(8, name).bytes(9, &raw_data);
+        self.initializers.push(tensor);
+    }
+
+    /// Name of a scalar initializer holding the value
+    fn constant(&mut self, value: f64) -> String {
+        let name = format!("constant_{}", value);
+        if !self.constants.contains(&name) {
+            self.initializer(&name, &[], &[value]);
+            self.constants.push(name.clone());
+        }
+        name
+    }
+
+    /// Nodes transforming the input like the normalization, giving the name of their output
+    fn normalization(&mut self, normalization: &Normalization, input: &str) -> String {
+        let output = "normalized_input".to_string();
+        match normalization {
+            Normalization::None => return input.to_string(),
+            Normalization::Unit => {
+                let pixel_max = self.constant(PIXEL_MAX);
+                self.node("Div", &[input, &pixel_max], &output, &[]);
+            }
+            Normalization::Symmetric => {
+                let pixel_max = self.constant(PIXEL_MAX);
+                let two = self.constant(2.0);
+                let one = self.constant(1.0);
+                self.node("Div", &[input, &pixel_max], "normalized_unit", &[]);
+                self.node("Mul", &["normalized_unit", &two], "normalized_double", &[]);
+                self.node("Sub", &["normalized_double", &one], &output, &[]);
+            }
+            Normalization::Standardize { mean, std } => {
+                self.initializer("normalization_mean", &[mean.len()], mean);
+                self.initializer("normalization_std", &[std.len()], std);
+                self.node(
+                    "Sub",
+                    &[input, "normalization_mean"],
+                    "normalized_centered",
+                    &[],
+                );
+                self.node(
+                    "Div",
+                    &["normalized_centered", "normalization_std"],
+                    &output,
+                    &[],
+                );
+            }
+        }
+        output
+    }
+
+    fn activation(
+        &mut self,
+        activation: &Activation,
+        input: &str,
+        output: &str,
+    ) -> Result<(), Error> {
+        match activation {
+            Activation::Sigmoid => self.node("Sigmoid", &[input], output, &[]),
+            Activation::Tanh => self.node("Tanh", &[input], output, &[]),
+            Activation::Relu => self.node("Relu", &[input], output, &[]),
+            Activation::LeakyRelu(slope) => self.node(
+                "LeakyRelu",
+                &[input],
+                output,
+                &[("alpha", Attribute::Float(*slope))],
+            ),
+            Activation::Elu(alpha) => self.node(
+                "Elu",
+                &[input],
+                output,
+                &[("alpha", Attribute::Float(*alpha))],
+            ),
+            Activation::Selu => self.node(
+                "Selu",
+                &[input],
+                output,
+                &[
+                    ("alpha", Attribute::Float(SELU_ALPHA)),
+                    ("gamma", Attribute::Float(SELU_SCALE)),
+                ],
+            ),
+            Activation::Gelu => {
+                // 0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3)))
+                let name = |step: &str| format!("{}_gelu_{}", output, step);
+                let cubic = self.constant(0.044715);
+                let scale = self.constant((2.0 / std::f64::consts::PI).sqrt());
+                let one = self.constant(1.0);
+                let half = self.constant(0.5);
+
+                self.node("Mul", &[input, input], &name("square"), &[]);
+                self.node("Mul", &[&name("square"), input], &name("cube"), &[]);
+                self.node("Mul", &[&name("cube"), &cubic], &name("cubic"), &[]);
+                self.node("Add", &[input, &name("cubic")], &name("inner"), &[]);
+                self.node("Mul", &[&name("inner"), &scale], &name("scaled"), &[]);
+                self.node("Tanh", &[&name("scaled")], &name("tanh"), &[]);
+                self.node("Add", &[&name("tanh"), &one], &name("shifted"), &[]);
+                self.node("Mul", &[input, &half], &name("half"), &[]);
+                self.node("Mul", &[&name("half"), &name("shifted")], output, &[]);
+            }
+            Activation::Swish => {
+                let sigmoid = format!("{}_swish_sigmoid", output);
+                self.node("Sigmoid", &[input], &sigmoid, &[]);
+                self.node("Mul", &[input, &sigmoid], output, &[]);
+            }
+            Activation::Softplus => self.node("Softplus", &[input], output, &[]),
+            Activation::Linear => self.node("Identity", &[input], output, &[]),
+            Activation::Softmax => {
+                self.node("Softmax", &[input], output, &[("axis", Attribute::Int(1))])
+            }
+            Activation::Custom(name) => {
+                return Err(Error::NotExportable(format!(
+                    "the custom activation {} has no ONNX equivalent",
+                    name
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A graph read from an ONNX model, made of the operators written by `encode`
+pub struct Graph {
+    nodes: Vec<Node>,
+    initializers: HashMap<String, Matrix>,
+    input: String,
+    output: String,
+}
+
+struct Node {
+    op_type: String,
+    inputs: Vec<String>,
+    output: String,
+    attributes: HashMap<String, Attribute>,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Graph, Error> {
+    let mut graph_bytes = None;
+    let mut opset_version = None;
+    for (field, value) in parse(bytes, "model")? {
+        match field {
+            7 => graph_bytes = value.as_bytes(),
+            8 => {
+                let mut domain = String::new();
+                let mut version = None;
+                for (field, value) in parse(value.as_bytes().unwrap_or_default(), "opset")? {
+                    match field {
+                        1 => domain = value.as_string().unwrap_or_default(),
+                        2 => version = value.as_int(),
+                        _ => {}
+                    }
+                }
+                if domain.is_empty() || domain == "ai.onnx" {
+                    opset_version = version;
+                }
+            }
+            _ => {}
+        }
+    }
+    match opset_version {
+        Some(version) if version >= 1 => {}
+        _ => return Err(invalid("the model doesn't import the default operator set")),
+    }
+
+    let mut graph = Graph {
+        nodes: Vec::new(),
+        initializers: HashMap::new(),
+        input: String::new(),
+        output: String::new(),
+    };
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for (field, value) in parse(
+        graph_bytes.ok_or_else(|| invalid("the model has no graph"))?,
+        "graph",
+    )? {
+        let value = value.as_bytes().unwrap_or_default();
+        match field {
+            1 => graph.nodes.push(decode_node(value)?),
+            5 => {
+                let (name, tensor) = decode_tensor(value)?;
+                graph.initializers.insert(name, tensor);
+            }
+            11 | 12 => {
+                let name = parse(value, "value info")?
+                    .iter()
+                    .find(|(field, _)| *field == 1)
+                    .and_then(|(_, name)| name.as_string())
+                    .ok_or_else(|| invalid("a graph input or output has no name"))?;
+                if field == 11 {
+                    inputs.push(name);
+                } else {
+                    outputs.push(name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the initializers can also be listed as inputs
+    inputs.retain(|name| !graph.initializers.contains_key(name));
+    match (inputs.as_slice(), outputs.as_slice()) {
+        ([input], [output]) => {
+            graph.input = input.clone();
+            graph.output = output.clone();
+        }
+        _ => {
+            return Err(invalid(
+                "the graph must have a single input and a single output",
+            ))
+        }
+    }
+
+    Ok(graph)
+}
+
+fn decode_node(bytes: &[u8]) -> Result<Node, Error> {
+    let mut node = Node {
+        op_type: String::new(),
+        inputs: Vec::new(),
+        output: String::new(),
+        attributes: HashMap::new(),
+    };
+    for (field, value) in parse(bytes, "node")? {
+        match field {
+            1 => node.inputs.push(value.as_string().unwrap_or_default()),
+            2 => node.output = value.as_string().unwrap_or_default(),
+            4 => node.op_type = value.as_string().unwrap_or_default(),
+            5 => {
+                let mut name = String::new();
+                let mut attribute = None;
+                for (field, value) in parse(value.as_bytes().unwrap_or_default(), "attribute")? {
+                    match field {
+                        1 => name = value.as_string().unwrap_or_default(),
+                        2 => {
+                            attribute = value.as_float().map(|value| Attribute::Float(value as f64))
+                        }
+                        3 => attribute = value.as_int().map(Attribute::Int),
+                        _ => {}
+                    }
+                }
+                if let Some(attribute) = attribute {
+                    node.attributes.insert(name, attribute);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+/// Name and values of a float tensor of at most 2 dimensions, the vectors being single rows
+fn decode_tensor(bytes: &[u8]) -> Result<(String, Matrix), Error> {
+    let mut dims = Vec::new();
+    let mut data_type = 0;
+    let mut name = String::new();
+    let mut values = Vec::new();
+    for (field, value) in parse(bytes, "tensor")? {
+        match field {
+            1 => dims.extend(value.as_ints().unwrap_or_default()),
+            2 => data_type = value.as_int().unwrap_or_default(),
+            // float_data, packed or not
+            4 => match value {
+                Value::Fixed32(bits) => values.push(f32::from_bits(bits) as f64),
+                _ => values.extend(float_values(value.as_bytes().unwrap_or_default())),
+            },
+            8 => name = value.as_string().unwrap_or_default(),
+            9 => values.extend(float_values(value.as_bytes().unwrap_or_default())),
+            _ => {}
+        }
+    }
+
+    if data_type != FLOAT {
+        return Err(invalid(&format!("the tensor {} doesn't hold floats", name)));
+    }
+    let (rows, cols) = match dims.as_slice() {
+        [] => (1, 1),
+        &[cols] => (1, cols as usize),
+        &[rows, cols] => (rows as usize, cols as usize),
+        _ => {
+            return Err(invalid(&format!(
+                "the tensor {} has too many dimensions",
+                name
+            )))
+        }
+    };
+    if values.len() != rows * cols {
+        return Err(invalid(&format!(
+            "the tensor {} has {} values instead of {}",
+            name,
+            values.len(),
+            rows * cols
+        )));
+    }
+
+    Ok((name, Matrix::from_vec(rows, cols, values)))
+}
+
+fn float_values(bytes: &[u8]) -> impl Iterator<Item = f64> + '_ {
+    bytes
+        .chunks_exact(4)
+        .map(|value| f32::from_le_bytes(value.try_into().unwrap()) as f64)
+}
+
+fn parse<'a>(bytes: &'a [u8], what: &str) -> Result<Vec<(u32, Value<'a>)>, Error> {
+    protobuf::parse(bytes).map_err(|error| invalid(&format!("{}: {}", what, error)))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidOnnx(message.to_string())
+}
+
+impl Graph {
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Run the graph on a batch of samples (one sample per row)
+    pub fn evaluate(&self, inputs: &Matrix) -> Result<Matrix, Error> {
+        let mut values: HashMap<&str, Matrix> = self
+            .initializers
+            .iter()
+            .map(|(name, tensor)| (name.as_str(), tensor.clone()))
+            .collect();
+        values.insert(&self.input, inputs.clone());
+
+        for node in &self.nodes {
+            let node_inputs = node
+                .inputs
+                .iter()
+                .map(|name| {
+                    values.get(name.as_str()).ok_or_else(|| {
+                        invalid(&format!(
+                            "the node {} uses the unknown tensor {}",
+                            node.output, name
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<&Matrix>, Error>>()?;
+            let output = node.evaluate(&node_inputs)?;
+            values.insert(&node.output, output);
+        }
+
+        values
+            .remove(self.output.as_str())
+            .ok_or_else(|| invalid("no node computes the output"))
+    }
+}
+
+impl Node {
+    fn float(&self, name: &str, default: f64) -> f64 {
+        match self.attributes.get(name) {
+            Some(Attribute::Float(value)) => *value,
+            Some(Attribute::Int(value)) => *value as f64,
+            None => default,
+        }
+    }
+
+    fn int(&self, name: &str, default: i64) -> i64 {
+        match self.attributes.get(name) {
+            Some(Attribute::Int(value)) => *value,
+            _ => default,
+        }
+    }
+
+    fn evaluate(&self, inputs: &[&Matrix]) -> Result<Matrix, Error> {
+        let expected_inputs = match self.op_type.as_str() {
+            "Gemm" => 2..=3,
+            "Add" | "Sub" | "Mul" | "Div" => 2..=2,
+            _ => 1..=1,
+        };
+        if !expected_inputs.contains(&inputs.len()) {
+            return Err(invalid(&format!(
+                "the {} node {} has {} inputs",
+                self.op_type,
+                self.output,
+                inputs.len()
+            )));
+        }
+        let x = inputs[0];
+
+        let output = match self.op_type.as_str() {
+            "Gemm" => {
+                let transpose = |matrix: &Matrix, name| {
+                    if self.int(name, 0) != 0 {
+                        matrix.transpose()
+                    } else {
+                        matrix.clone()
+                    }
+                };
+                let (a, b) = (transpose(x, "transA"), transpose(inputs[1], "transB"));
+                if a.cols != b.rows {
+                    return Err(invalid(&format!(
+                        "the shapes of the Gemm node {} don't match",
+                        self.output
+                    )));
+                }
+                let mut product = a.multiply(&b);
+                product.scale_in_place(self.float("alpha", 1.0));
+                match inputs.get(2) {
+                    Some(c) => {
+                        let mut c = (*c).clone();
+                        c.scale_in_place(self.float("beta", 1.0));
+                        self.broadcast(&product, &c, |a, b| a + b)?
+                    }
+                    None => product,
+                }
+            }
+            "Add" => self.broadcast(x, inputs[1], |a, b| a + b)?,
+            "Sub" => self.broadcast(x, inputs[1], |a, b| a - b)?,
+            "Mul" => self.broadcast(x, inputs[1], |a, b| a * b)?,
+            "Div" => self.broadcast(x, inputs[1], |a, b| a / b)?,
+            "Identity" => x.clone(),
+            "Sigmoid" => Activation::Sigmoid.apply(x),
+            "Tanh" => Activation::Tanh.apply(x),
+            "Relu" => Activation::Relu.apply(x),
+            "Softplus" => Activation::Softplus.apply(x),
+            "LeakyRelu" => Activation::LeakyRelu(self.float("alpha", 0.01)).apply(x),
+            "Elu" => Activation::Elu(self.float("alpha", 1.0)).apply(x),
+            "Selu" => {
+                let alpha = self.float("alpha", 1.673_263_192_176_818_8);
+                let gamma = self.float("gamma", 1.050_701_022_148_132_3);
+                x.map(&|x| gamma * if x > 0.0 { x } else { alpha * (x.exp() - 1.0) })
+            }
+            "Softmax" => match self.int("axis", -1) {
+                1 | -1 => Activation::Softmax.apply(x),
+                axis => return Err(invalid(&format!("unsupported softmax axis: {}", axis))),
+            },
+            op_type => return Err(invalid(&format!("unsupported operator: {}", op_type))),
+        };
+        Ok(output)
+    }
+
+    /// Combine two tensors of the same shape, or a tensor with a row or a scalar
+    fn broadcast(
+        &self,
+        a: &Matrix,
+        b: &Matrix,
+        function: impl Fn(f64, f64) -> f64,
+    ) -> Result<Matrix, Error> {
+        let (large, small, swapped) = if a.data.len() >= b.data.len() {
+            (a, b, false)
+        } else {
+            (b, a, true)
+        };
+        let row_length = if small.rows == large.rows && small.cols == large.cols {
+            large.data.len()
+        } else if small.rows == 1 && (small.cols == large.cols || small.cols == 1) {
+            small.cols
+        } else {
+            return Err(invalid(&format!(
+                "the shapes of the node {} can't be broadcast",
+                self.output
+            )));
+        };
+
+        let mut output = large.clone();
+        for (i, value) in output.data.iter_mut().enumerate() {
+            let other = small.data[i % row_length % small.data.len()];
+            *value = if swapped {
+                function(other, *value)
+            } else {
+                function(*value, other)
+            };
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::network::network::Network;
+
+    const SEED: u64 = 42;
+    const SIZES: [usize; 3] = [4, 3, 2];
+
+    /// Operator type, inputs, output and integer attributes (name, value, type) of a node
+    type NodeFields = (String, Vec<String>, String, Vec<(String, u64, u64)>);
+
+    /// Fields of a message, read with the field numbers of `onnx.proto`
+    fn fields(bytes: &[u8]) -> Vec<(u32, Value<'_>)> {
+        protobuf::parse(bytes).unwrap()
+    }
+
+    fn field<'a>(fields: &[(u32, Value<'a>)], number: u32) -> Vec<Value<'a>> {
+        fields
+            .iter()
+            .filter(|(field, _)| *field == number)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    fn single<'a>(fields: &[(u32, Value<'a>)], number: u32) -> Value<'a> {
+        let values = field(fields, number);
+        assert_eq!(values.len(), 1, "field {} isn't a single value", number);
+        values[0]
+    }
+
+    fn string(fields: &[(u32, Value)], number: u32) -> String {
+        single(fields, number).as_string().unwrap()
+    }
+
+    fn varint(fields: &[(u32, Value)], number: u32) -> u64 {
+        match single(fields, number) {
+            Value::Varint(value) => value,
+            value => panic!("field {} isn't a varint: {:?}", number, value),
+        }
+    }
+
+    /// Name, `batch` dim_param and size of a `ValueInfoProto`
+    fn value_info(bytes: &[u8]) -> (String, String, u64) {
+        let info = fields(bytes);
+        let type_proto = fields(single(&info, 2).as_bytes().unwrap());
+        let tensor_type = fields(single(&type_proto, 1).as_bytes().unwrap());
+        assert_eq!(varint(&tensor_type, 1), FLOAT as u64);
+        let shape = fields(single(&tensor_type, 2).as_bytes().unwrap());
+        let dims = field(&shape, 1);
+        assert_eq!(dims.len(), 2);
+        let batch = fields(dims[0].as_bytes().unwrap());
+        let size = fields(dims[1].as_bytes().unwrap());
+        assert!(field(&batch, 1).is_empty());
+        (string(&info, 1), string(&batch, 2), varint(&size, 1))
+    }
+
+    /// Layers holding the weights drawn by `Network::new_seeded`
+    fn seeded_layers() -> Vec<Layer> {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        SIZES
+            .windows(2)
+            .map(|sizes| Layer::new_with_rng(sizes[0], sizes[1], Activation::Tanh, &mut rng))
+            .collect()
+    }
+
+    fn seeded_network() -> Network {
+        Network::new_seeded(
+            &SIZES,
+            &0.1,
+            vec![Activation::Tanh, Activation::Sigmoid],
+            SEED,
+        )
+    }
+
+    fn samples() -> Matrix {
+        Matrix::random_with(5, SIZES[0], &mut StdRng::seed_from_u64(SEED + 1))
+    }
+
+    #[test]
+    fn export_writes_the_onnx_structure() {
+        let bytes = seeded_network().to_onnx().unwrap();
+        // ir_version, field 1 as a varint
+        assert_eq!(&bytes[..2], &[0x08, 0x07]);
+
+        let model = fields(&bytes);
+        assert_eq!(varint(&model, 1), 7);
+        let opset = fields(single(&model, 8).as_bytes().unwrap());
+        assert_eq!(string(&opset, 1), "");
+        assert_eq!(varint(&opset, 2), 13);
+
+        let graph = fields(single(&model, 7).as_bytes().unwrap());
+        let inputs = field(&graph, 11);
+        let outputs = field(&graph, 12);
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(
+            value_info(inputs[0].as_bytes().unwrap()),
+            ("input".to_string(), "batch".to_string(), 4)
+        );
+        assert_eq!(
+            value_info(outputs[0].as_bytes().unwrap()),
+            ("output".to_string(), "batch".to_string(), 2)
+        );
+
+        let nodes: Vec<NodeFields> = field(&graph, 1)
+            .iter()
+            .map(|node| {
+                let node = fields(node.as_bytes().unwrap());
+                let inputs = field(&node, 1)
+                    .iter()
+                    .map(|input| input.as_string().unwrap())
+                    .collect();
+                let attributes = field(&node, 5)
+                    .iter()
+                    .map(|attribute| {
+                        let attribute = fields(attribute.as_bytes().unwrap());
+                        (
+                            string(&attribute, 1),
+                            varint(&attribute, 3),
+                            varint(&attribute, 20),
+                        )
+                    })
+                    .collect();
+                (string(&node, 4), inputs, string(&node, 2), attributes)
+            })
+            .collect();
+        let gemm = |i: usize, input: &str, output: &str| {
+            (
+                "Gemm".to_string(),
+                vec![
+                    input.to_string(),
+                    format!("layer{}_weights", i),
+                    format!("layer{}_biases", i),
+                ],
+                output.to_string(),
+                vec![("transB".to_string(), 1, ATTRIBUTE_INT as u64)],
+            )
+        };
+        let activation = |op_type: &str, input: &str, output: &str| {
+            (
+                op_type.to_string(),
+                vec![input.to_string()],
+                output.to_string(),
+                vec![],
+            )
+        };
+        assert_eq!(
+            nodes,
+            vec![
+                gemm(0, "input", "layer0_weighted_output"),
+                activation("Tanh", "layer0_weighted_output", "layer0_output"),
+                gemm(1, "layer0_output", "layer1_weighted_output"),
+                activation("Sigmoid", "layer1_weighted_output", "output"),
+            ]
+        );
+
+        let initializers: Vec<(String, Vec<i64>, Vec<f32>)> = field(&graph, 5)
+            .iter()
+            .map(|tensor| {
+                let tensor = fields(tensor.as_bytes().unwrap());
+                assert_eq!(varint(&tensor, 2), FLOAT as u64);
+                let dims = field(&tensor, 1)
+                    .iter()
+                    .flat_map(|dims| dims.as_ints().unwrap())
+                    .collect();
+                let data = single(&tensor, 9)
+                    .as_bytes()
+                    .unwrap()
+                    .chunks_exact(4)
+                    .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+                    .collect();
+                (string(&tensor, 8), dims, data)
+            })
+            .collect();
+        let mut expected = Vec::new();
+        for (i, layer) in seeded_layers().iter().enumerate() {
+            let to_f32 = |matrix: &Matrix| matrix.data.iter().map(|&x| x as f32).collect();
+            expected.push((
+                format!("layer{}_weights", i),
+                vec![layer.size_out as i64, layer.size_in as i64],
+                to_f32(&layer.weights),
+            ));
+            expected.push((
+                format!("layer{}_biases", i),
+                vec![layer.size_out as i64],
+                to_f32(&layer.biases),
+            ));
+        }
+        assert_eq!(initializers, expected);
+    }
+
+    #[test]
+    fn exported_graph_matches_the_network() {
+        let mut network = seeded_network();
+        let bytes = network.to_onnx().unwrap();
+        let inputs = samples();
+        let expected = network.feed_forwards_batch(inputs.clone());
+
+        // Gemm with transB: input · weightsᵀ + biases, from the f32 weights
+        let mut outputs = inputs.clone();
+        for (layer, activation) in seeded_layers()
+            .iter()
+            .zip([Activation::Tanh, Activation::Sigmoid])
+        {
+            let round = |matrix: &Matrix| matrix.map(&|x| x as f32 as f64);
+            outputs = outputs.multiply(&round(&layer.weights).transpose());
+            outputs.add_to_rows(&round(&layer.biases).data);
+            outputs = activation.apply(&outputs);
+        }
+        let found = decode(&bytes).unwrap().evaluate(&inputs).unwrap();
+
+        for outputs in [&outputs, &found] {
+            assert_eq!((outputs.rows, outputs.cols), (expected.rows, expected.cols));
+            for (found, expected) in outputs.data.iter().zip(&expected.data) {
+                assert!((found - expected).abs() < TOLERANCE);
+            }
+        }
+        for i in 0..inputs.rows {
+            let output = network.feed_forwards(inputs.row(i));
+            assert_eq!(output, expected.row(i));
+        }
+    }
+}
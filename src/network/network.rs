@@ -1,130 +1,279 @@
 use std::{
     fs::File,
     io::{Read, Write},
+    thread,
 };
 
 use progress_bar::*;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 
-use crate::matrix::matrix::Matrix;
+use crate::{error::Error, matrix::matrix::Matrix};
 
 use super::{
-    activations::{self, Activation},
+    activations::Activation,
+    gradient_check::{self, GradientCheck},
     layer::Layer,
+    losses::{Loss, SumSquaredError},
+    metadata::Metadata,
+    model_file, model_json, model_npz, model_onnx,
+    normalization::{self, Normalization},
+    optimizers::{Optimizer, Sgd},
+    prediction::Prediction,
+    schedules::{Constant, Schedule},
 };
 
+/// Inputs and targets of a single training batch
+type Batch = (Matrix, Matrix);
+
+/// Smallest share of a batch given to a training thread, smaller shares costing more to
+/// synchronize than to compute
+const MIN_SAMPLES_PER_THREAD: usize = 32;
+
 #[derive(Clone)]
-pub struct Network<'a> {
-    layers: Vec<Layer<'a>>,
+pub struct Network {
+    layers: Vec<Layer>,
     layer_count: usize,
     learning_rate: f64,
+    /// Number of threads sharing the samples of each batch
+    thread_count: usize,
+    /// Copies of the layers used by the training threads other than the calling one, kept
+    /// between the batches so only the parameters are copied
+    workers: Vec<Vec<Layer>>,
+    optimizer: Box<dyn Optimizer>,
+    schedule: Box<dyn Schedule>,
+    loss: Box<dyn Loss>,
+    /// Number of batches learned, given to the schedule
+    step: usize,
+    /// Samples measuring the cost given to the schedule
+    validation: Option<Batch>,
+    /// Batches learned between two measures of the validation cost, after each pass over the
+    /// training data when `None`
+    validation_interval: Option<usize>,
+    /// Transform of the raw inputs given to the network, before the first layer
+    normalization: Normalization,
+    metadata: Metadata,
 }
 
-impl Network<'_> {
-    pub fn new<'a>(
-        layers_struct: &'a Vec<usize>,
-        learning_rate: &'a f64,
-        activation_function: Activation<'a>,
-    ) -> Network<'a> {
+impl Network {
+    /// Create a network using the same activation for every layer
+    #[allow(unused)]
+    pub fn new(
+        layers_struct: &[usize],
+        learning_rate: &f64,
+        activation_function: Activation,
+    ) -> Network {
+        Network::with_activations(
+            layers_struct,
+            learning_rate,
+            vec![activation_function; layers_struct.len() - 1],
+        )
+    }
+
+    /// Create a network with a different activation for each layer (hidden layers first)
+    pub fn with_activations(
+        layers_struct: &[usize],
+        learning_rate: &f64,
+        activations: Vec<Activation>,
+    ) -> Network {
+        Network::new_with_rng(layers_struct, learning_rate, activations, &mut thread_rng())
+    }
+
+    /// Create a network like `with_activations`, whose initial weights only depend on the seed
+    pub fn new_seeded(
+        layers_struct: &[usize],
+        learning_rate: &f64,
+        activations: Vec<Activation>,
+        seed: u64,
+    ) -> Network {
+        Network::new_with_rng(
+            layers_struct,
+            learning_rate,
+            activations,
+            &mut StdRng::seed_from_u64(seed),
+        )
+    }
+
+    fn new_with_rng(
+        layers_struct: &[usize],
+        learning_rate: &f64,
+        activations: Vec<Activation>,
+        rng: &mut impl Rng,
+    ) -> Network {
+        assert_eq!(
+            activations.len(),
+            layers_struct.len() - 1,
+            "Every layer needs an activation"
+        );
         let mut layers = Vec::<Layer>::new();
 
-        for i in 1..layers_struct.len() {
-            layers.push(Layer::new(
-                layers_struct[i - 1],
+        for (i, activation) in activations.into_iter().enumerate() {
+            layers.push(Layer::new_with_rng(
                 layers_struct[i],
-                activation_function.clone(),
+                layers_struct[i + 1],
+                activation,
+                rng,
             ))
         }
 
+        Network::from_layers(layers, learning_rate)
+    }
+
+    fn from_layers(layers: Vec<Layer>, learning_rate: &f64) -> Network {
         Network {
+            layer_count: layers.len(),
             layers,
-            layer_count: layers_struct.len() - 1,
-            learning_rate: learning_rate.clone(),
+            learning_rate: *learning_rate,
+            thread_count: 1,
+            workers: Vec::new(),
+            optimizer: Box::new(Sgd),
+            schedule: Box::new(Constant),
+            loss: Box::new(SumSquaredError),
+            step: 0,
+            validation: None,
+            validation_interval: None,
+            normalization: Normalization::None,
+            metadata: Metadata::default(),
         }
     }
 
-    pub fn load_from_file<'a>(
-        path: &str,
-        learning_rate: &f64,
-        activation_function: Activation<'a>,
-    ) -> Network<'a> {
-        let mut file = File::open(path).expect("Can't open the model file");
+    /// Replace the activation of every layer, from the first hidden layer to the output layer
+    pub fn set_activations(&mut self, activations: Vec<Activation>) {
+        assert_eq!(activations.len(), self.layer_count);
+        for (layer, activation) in self.layers.iter_mut().zip(activations) {
+            layer.set_activation(activation);
+        }
+    }
 
-        // layer_count
-        let mut layer_count_buf: [u8; 2] = [0, 2];
-        file.read_exact(&mut layer_count_buf)
-            .expect("Can't read the layer_count");
-        let layer_count = u16::from_be_bytes(layer_count_buf) as usize - 1;
+    /// Use the optimizer to update the parameters while learning (plain SGD by default)
+    pub fn set_optimizer(&mut self, optimizer: Box<dyn Optimizer>) {
+        self.optimizer = optimizer;
+    }
 
-        // layers_size
-        let mut layers = Vec::<Layer>::new();
-        let mut layers_size: Vec<usize> = vec![0];
-        for _ in 0..layer_count + 1 {
-            let mut layer_size_buf: [u8; 2] = [0; 2];
-            file.read_exact(&mut layer_size_buf)
-                .expect("Can't read the layer size");
-            let size_out = u16::from_be_bytes(layer_size_buf) as usize;
-            let layer = Layer::new(
-                layers_size.last().expect("No precedent layer").clone(),
-                size_out,
-                activation_function.clone(),
-            );
-            layers_size.push(size_out);
-            layers.push(layer);
-        }
+    /// Change the learning rate at each batch (constant by default)
+    pub fn set_schedule(&mut self, schedule: Box<dyn Schedule>) {
+        self.schedule = schedule;
+    }
 
-        layers.remove(0);
-
-        // weights and biases for each layer
-        for layer_index in 0..layer_count {
-            let mut weights =
-                Matrix::zeros(layers[layer_index].size_out, layers[layer_index].size_in);
-            for i in 0..layers[layer_index].size_out {
-                for j in 0..layers[layer_index].size_in {
-                    let mut weight_buf: [u8; 8] = [0; 8];
-                    file.read_exact(&mut weight_buf)
-                        .expect("Can't read the weights");
-                    weights.data[i][j] = f64::from_be_bytes(weight_buf);
-                }
-            }
-            let mut biases = Matrix::zeros(layers[layer_index].size_out, 1);
-            for i in 0..layers[layer_index].size_out {
-                let mut bias_buf: [u8; 8] = [0; 8];
-                file.read_exact(&mut bias_buf)
-                    .expect("Can't read the biases");
-                biases.data[i][0] = f64::from_be_bytes(bias_buf);
-            }
-            layers[layer_index].weights = weights;
-            layers[layer_index].biases = biases;
-        }
+    /// Cost minimized while learning (squared error by default)
+    pub fn set_loss(&mut self, loss: Box<dyn Loss>) {
+        self.loss = loss;
+    }
 
-        Network {
-            layer_count,
-            layers,
-            learning_rate: learning_rate.clone(),
-        }
+    /// Samples, unused for learning, whose average cost is observed by the schedule while
+    /// training with `train_with_batch` (see `set_validation_interval`)
+    pub fn set_validation_data(&mut self, inputs: &[Vec<f64>], targets: &[Vec<f64>]) {
+        self.validation = if inputs.is_empty() {
+            None
+        } else {
+            Some((
+                Matrix::from(inputs.to_vec()),
+                Matrix::from(targets.to_vec()),
+            ))
+        };
     }
 
-    pub fn feed_forwards(&mut self, input: &Vec<f64>) -> Vec<f64> {
-        let mut current: Vec<f64> = input.clone();
-        for layer_index in 0..self.layers.len() {
-            current = self.layers[layer_index].compute_output(current);
+    /// Observe the validation cost every `batches` batches instead of after each pass over the
+    /// training data, a pass being often longer than the whole training
+    pub fn set_validation_interval(&mut self, batches: usize) {
+        self.validation_interval = Some(batches.max(1));
+    }
+
+    /// Transform every input given to the network, while learning and while recognizing
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        if let Some(size) = normalization.input_size() {
+            assert_eq!(size, self.input_size(), "Wrong normalization size");
         }
-        current
+        self.normalization = normalization;
+    }
+
+    pub fn normalization(&self) -> &Normalization {
+        &self.normalization
+    }
+
+    /// Split the samples of each batch between at most `thread_count` threads while learning,
+    /// each one getting at least `MIN_SAMPLES_PER_THREAD` samples. The result only depends on
+    /// the thread count and the batch size, not on the threads scheduling.
+    pub fn set_thread_count(&mut self, thread_count: usize) {
+        self.thread_count = thread_count.max(1);
+    }
+
+    /// Read a model written by `save`, in the current or in the legacy format
+    pub fn load(reader: &mut impl Read, learning_rate: &f64) -> Result<Network, Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (layers, metadata, normalization) = model_file::decode(&bytes)?;
+
+        let mut network = Network::from_layers(layers, learning_rate);
+        network.metadata = metadata;
+        network.normalization = normalization;
+        Ok(network)
+    }
+
+    pub fn load_from_file(path: &str, learning_rate: &f64) -> Result<Network, Error> {
+        Network::load(&mut File::open(path)?, learning_rate)
+    }
+
+    /// Read a model written by `to_json`
+    pub fn from_json(text: &str, learning_rate: &f64) -> Result<Network, Error> {
+        let (layers, metadata, normalization) = model_json::decode(text)?;
+
+        let mut network = Network::from_layers(layers, learning_rate);
+        network.metadata = metadata;
+        network.normalization = normalization;
+        Ok(network)
+    }
+
+    /// Read the parameters written by `to_npz` or by NumPy
+    pub fn from_npz(bytes: &[u8], learning_rate: &f64) -> Result<Network, Error> {
+        let (layers, normalization) = model_npz::decode(bytes)?;
+
+        let mut network = Network::from_layers(layers, learning_rate);
+        network.normalization = normalization;
+        Ok(network)
+    }
+
+    /// Information about the training of the model, saved with it
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    pub fn feed_forwards(&mut self, input: &[f64]) -> Vec<f64> {
+        self.feed_forwards_batch(Matrix::from_vec(1, input.len(), input.to_vec()))
+            .data
+    }
+
+    /// Compute the outputs of a whole batch at once, one sample per row
+    pub fn feed_forwards_batch(&mut self, inputs: Matrix) -> Matrix {
+        feed_forwards_layers(&mut self.layers, self.normalization.apply(inputs))
+    }
+
+    /// Number of values expected by the input layer
+    pub fn input_size(&self) -> usize {
+        self.layers[0].size_in
+    }
+
+    /// Number of layers with weights, the input layer excluded
+    pub fn layer_count(&self) -> usize {
+        self.layer_count
     }
 
     pub fn train_with_batch(
         &mut self,
-        inputs: &Vec<Vec<f64>>,
-        targets: &Vec<Vec<f64>>,
-        epochs: usize,
+        inputs: &[Vec<f64>],
+        targets: &[Vec<f64>],
+        steps: usize,
         batch_size: usize,
-    ) -> () {
+    ) {
         println!(
             "Splitting the data into batches of {} elements...",
             batch_size
         );
         // create all the batches
-        let mut data: Vec<(Vec<Vec<f64>>, Vec<Vec<f64>>)> = Vec::new();
+        let mut data: Vec<Batch> = Vec::new();
 
         for i in 0..inputs.len() / batch_size {
             let mut batch_inputs: Vec<Vec<f64>> = Vec::new();
@@ -133,84 +282,252 @@ impl Network<'_> {
                 batch_inputs.push(inputs[i * batch_size + j].clone());
                 batch_targets.push(targets[i * batch_size + j].clone());
             }
-            data.push((batch_inputs, batch_targets));
+            data.push((Matrix::from(batch_inputs), Matrix::from(batch_targets)));
         }
 
         println!("Starting the learnig process...");
-        for i in 0..epochs {
+        init_progress_bar(steps);
+        set_progress_bar_action("Learning", Color::Blue, Style::Bold);
+        for i in 0..steps {
             let batch_index = i % data.len();
-            self.learn(&data[batch_index].0, &data[batch_index].1, i + 1, epochs);
-        }
-    }
+            let loss = self.learn(&data[batch_index].0, &data[batch_index].1);
+            set_progress_bar_action(
+                format!("loss: {:.4}", loss).as_str(),
+                Color::Blue,
+                Style::Bold,
+            );
 
-    pub fn train(&mut self, inputs: &Vec<Vec<f64>>, targets: &Vec<Vec<f64>>, epochs: usize) -> () {
-        println!("Starting the learnig process...");
-        for i in 0..epochs {
-            self.learn(&inputs, &targets, i + 1, epochs);
+            let observed = match self.validation_interval {
+                Some(interval) => (i + 1) % interval == 0,
+                None => batch_index == data.len() - 1,
+            };
+            if observed {
+                if let Some((validation_inputs, validation_targets)) = self.validation.take() {
+                    let cost = self.cost(&validation_inputs, &validation_targets)
+                        / validation_inputs.rows as f64;
+                    self.schedule.observe(cost);
+                    self.validation = Some((validation_inputs, validation_targets));
+                }
+            }
+            inc_progress_bar();
         }
+        set_progress_bar_action("Success", Color::Green, Style::Bold);
+        finalize_progress_bar();
+
+        self.metadata.batches += steps as u64;
+        self.metadata.date = Metadata::now();
     }
 
-    pub fn learn(
-        &mut self,
-        inputs_batch: &Vec<Vec<f64>>,
-        targets_batch: &Vec<Vec<f64>>,
-        c_epoch: usize,
-        epochs: usize,
-    ) {
-        init_progress_bar(inputs_batch.len());
-        set_progress_bar_action(
-            format!("{}/{}", c_epoch, epochs).as_str(),
-            Color::Blue,
-            Style::Bold,
-        );
-        for i in 0..inputs_batch.len() {
-            self.update_all_gradients(&inputs_batch[i], &targets_batch[i]);
+    #[allow(unused)]
+    pub fn train(&mut self, inputs: &[Vec<f64>], targets: &[Vec<f64>], epochs: usize) {
+        let inputs = Matrix::from(inputs.to_vec());
+        let targets = Matrix::from(targets.to_vec());
+
+        println!("Starting the learnig process...");
+        init_progress_bar(epochs);
+        set_progress_bar_action("Learning", Color::Blue, Style::Bold);
+        for _ in 0..epochs {
+            let loss = self.learn(&inputs, &targets);
+            set_progress_bar_action(
+                format!("loss: {:.4}", loss).as_str(),
+                Color::Blue,
+                Style::Bold,
+            );
             inc_progress_bar();
         }
-
         set_progress_bar_action("Success", Color::Green, Style::Bold);
+        finalize_progress_bar();
+
+        // the whole data is a single batch
+        self.metadata.batches += epochs as u64;
+        self.metadata.date = Metadata::now();
+    }
+
+    /// Run a gradient descent step on a batch (one sample per row) and return its average loss,
+    /// measured before the step
+    pub fn learn(&mut self, inputs_batch: &Matrix, targets_batch: &Matrix) -> f64 {
+        let thread_count = self
+            .thread_count
+            .min(inputs_batch.rows / MIN_SAMPLES_PER_THREAD)
+            .max(1);
+        let inputs = self.normalization.apply(inputs_batch.clone());
+        let cost = if thread_count <= 1 {
+            backpropagate(&mut self.layers, &inputs, targets_batch, self.loss.as_ref())
+        } else {
+            self.update_all_gradients_parallel(&inputs, targets_batch, thread_count)
+        };
+
+        let learning_rate = self.schedule.learning_rate(self.learning_rate, self.step);
+        self.step += 1;
 
         for i in 0..self.layer_count {
-            self.layers[i].apply_gradients(&(self.learning_rate / inputs_batch.len() as f64));
+            self.layers[i].apply_gradients(
+                self.optimizer.as_mut(),
+                i,
+                learning_rate,
+                inputs_batch.rows,
+            );
             self.layers[i].clear_gradients();
         }
-        finalize_progress_bar();
+
+        cost / inputs_batch.rows as f64
+    }
+
+    /// Accumulate the gradients of the batch and return its total cost
+    pub fn update_all_gradients(&mut self, inputs: &Matrix, targets: &Matrix) -> f64 {
+        let inputs = self.normalization.apply(inputs.clone());
+        backpropagate(&mut self.layers, &inputs, targets, self.loss.as_ref())
+    }
+
+    /// Total cost of the batch, the function whose gradients are computed by backpropagation
+    pub fn cost(&mut self, inputs: &Matrix, targets: &Matrix) -> f64 {
+        self.feed_forwards_batch(inputs.clone());
+
+        self.layers[self.layer_count - 1].cost(targets, self.loss.as_ref())
     }
 
-    pub fn update_all_gradients(&mut self, input: &Vec<f64>, target: &Vec<f64>) {
-        self.feed_forwards(&input);
+    /// Compare the backpropagated gradients of every layer with a finite differences estimate
+    /// computed by moving each weight and bias by `epsilon`. Meant for small networks, the cost
+    /// is computed twice per parameter.
+    pub fn gradient_check(
+        &mut self,
+        inputs: &Matrix,
+        targets: &Matrix,
+        epsilon: f64,
+    ) -> Vec<GradientCheck> {
+        for layer in self.layers.iter_mut() {
+            layer.clear_gradients();
+        }
+        self.update_all_gradients(inputs, targets);
 
-        // update the gradient of the output layer
-        let mut node_values =
-            self.layers[self.layer_count - 1].get_output_layer_node_value(&target);
-        self.layers[self.layer_count - 1].update_gradients(&node_values);
+        let mut checks = Vec::new();
+        for layer_index in 0..self.layer_count {
+            let (analytic_w, analytic_b) = self.layers[layer_index].gradients();
+            let (analytic_w, analytic_b) = (analytic_w.data.clone(), analytic_b.data.clone());
+
+            let mut numeric_w = vec![0.0; analytic_w.len()];
+            for (i, numeric) in numeric_w.iter_mut().enumerate() {
+                *numeric = self.finite_difference(inputs, targets, epsilon, &|network| {
+                    &mut network.layers[layer_index].weights.data[i]
+                });
+            }
+
+            let mut numeric_b = vec![0.0; analytic_b.len()];
+            for (i, numeric) in numeric_b.iter_mut().enumerate() {
+                *numeric = self.finite_difference(inputs, targets, epsilon, &|network| {
+                    &mut network.layers[layer_index].biases.data[i]
+                });
+            }
+
+            checks.push(GradientCheck {
+                layer: layer_index,
+                weights_error: gradient_check::relative_error(&analytic_w, &numeric_w),
+                biases_error: gradient_check::relative_error(&analytic_b, &numeric_b),
+            });
+        }
 
-        // update the gradient of the hidden layer
-        for i in (0..self.layer_count - 2).rev() {
-            let old_layer_weights = &self.clone().layers[i + 1].weights;
-            let hidden_layer = &mut self.layers[i];
-            node_values =
-                hidden_layer.get_hidden_layer_node_value(&old_layer_weights, &node_values);
-            self.layers[i].update_gradients(&node_values);
+        for layer in self.layers.iter_mut() {
+            layer.clear_gradients();
         }
+
+        checks
     }
 
-    #[allow(unused)]
-    pub fn recognize(&mut self, input: &Vec<f64>) -> u8 {
-        let output = self.feed_forwards(input);
-        let mut max_index = 0;
-        for i in 1..output.len() {
-            if output[i] > output[max_index] {
-                max_index = i;
+    /// (cost(p + epsilon) - cost(p - epsilon)) / (2 * epsilon) for the selected parameter p
+    fn finite_difference(
+        &mut self,
+        inputs: &Matrix,
+        targets: &Matrix,
+        epsilon: f64,
+        parameter: &dyn for<'b> Fn(&'b mut Self) -> &'b mut f64,
+    ) -> f64 {
+        let original = *parameter(self);
+
+        *parameter(self) = original + epsilon;
+        let cost_plus = self.cost(inputs, targets);
+        *parameter(self) = original - epsilon;
+        let cost_minus = self.cost(inputs, targets);
+        *parameter(self) = original;
+
+        (cost_plus - cost_minus) / (2.0 * epsilon)
+    }
+
+    /// Compute the gradients of contiguous parts of the batch (already normalized), the first
+    /// one on the layers of the network and the others on the copies of the worker threads,
+    /// then sum them in the order of the parts so the result doesn't depend on the scheduling
+    fn update_all_gradients_parallel(
+        &mut self,
+        inputs: &Matrix,
+        targets: &Matrix,
+        thread_count: usize,
+    ) -> f64 {
+        let chunk_size = inputs.rows.div_ceil(thread_count);
+        let chunks: Vec<(usize, usize)> = (0..inputs.rows)
+            .step_by(chunk_size)
+            .map(|start| (start, (start + chunk_size).min(inputs.rows)))
+            .collect();
+
+        while self.workers.len() < chunks.len() - 1 {
+            self.workers.push(self.layers.clone());
+        }
+        for worker_layers in &mut self.workers[..chunks.len() - 1] {
+            for (worker_layer, layer) in worker_layers.iter_mut().zip(&self.layers) {
+                worker_layer.copy_parameters(layer);
+                worker_layer.clear_gradients();
+            }
+        }
+
+        let loss = self.loss.as_ref();
+        let layers = &mut self.layers;
+        let (cost, worker_costs) = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .workers
+                .iter_mut()
+                .zip(&chunks[1..])
+                .map(|(worker_layers, &(start, end))| {
+                    scope.spawn(move || {
+                        backpropagate(
+                            worker_layers,
+                            &inputs.rows_range(start, end),
+                            &targets.rows_range(start, end),
+                            loss,
+                        )
+                    })
+                })
+                .collect();
+
+            let (start, end) = chunks[0];
+            let cost = backpropagate(
+                layers,
+                &inputs.rows_range(start, end),
+                &targets.rows_range(start, end),
+                loss,
+            );
+            let worker_costs: Vec<f64> = handles
+                .into_iter()
+                .map(|handle| handle.join().expect("A training thread panicked"))
+                .collect();
+            (cost, worker_costs)
+        });
+
+        let mut total_cost = cost;
+        for (worker_layers, worker_cost) in self.workers.iter().zip(worker_costs) {
+            for (layer, worker_layer) in self.layers.iter_mut().zip(worker_layers) {
+                layer.add_gradients(worker_layer);
             }
+            total_cost += worker_cost;
         }
 
-        println!("{:?}", output);
+        total_cost
+    }
 
-        max_index as u8 + 1
+    /// Classify the input, with the probability of every class
+    pub fn recognize(&mut self, input: &[f64]) -> Prediction {
+        self.feed_forwards(input);
+        Prediction::from_logits(self.layers[self.layer_count - 1].weighted_output())
     }
 
-    pub fn test_accuracy(&mut self, inputs: &Vec<Vec<f64>>, targets: &Vec<Vec<f64>>) -> f64 {
+    pub fn test_accuracy(&mut self, inputs: &[Vec<f64>], targets: &[Vec<f64>]) -> f64 {
         let mut success_count = 0.0;
 
         init_progress_bar(inputs.len());
@@ -251,34 +568,84 @@ impl Network<'_> {
         accuracy
     }
 
-    pub fn save(&self, path: &str) -> std::io::Result<()> {
-        let mut file = File::create(&path)?;
+    pub fn save(&self, writer: &mut impl Write) -> Result<(), Error> {
+        writer.write_all(&model_file::encode(
+            &self.layers,
+            &self.metadata,
+            &self.normalization,
+        ))?;
+        writer.flush()?;
+        Ok(())
+    }
 
-        // layer count
-        file.write(&(self.layer_count as u16 + 1).to_be_bytes())
-            .expect("Can't write the layer_count to the saved file");
+    pub fn save_to_file(&self, path: &str) -> Result<(), Error> {
+        self.save(&mut File::create(path)?)
+    }
 
-        // layers size
-        file.write(&(self.layers[0].size_in as u16).to_be_bytes())
-            .expect("Can't save the input layer");
-        for i in 0..self.layer_count {
-            file.write(&(self.layers[i].size_out as u16).to_be_bytes())
-                .expect("Can't save layers");
-        }
+    /// The weights, biases, activations and normalization as a NumPy `.npz` archive
+    pub fn to_npz(&self) -> Vec<u8> {
+        model_npz::encode(&self.layers, &self.normalization)
+    }
 
-        for layer_index in 0..self.layer_count {
-            for i in 0..self.layers[layer_index].weights.data.len() {
-                for j in 0..self.layers[layer_index].weights.data[i].len() {
-                    file.write(&(self.layers[layer_index].weights.data[i][j].to_be_bytes()))
-                        .expect("Can't write weights to the save");
-                }
-            }
-            for i in 0..self.layers[layer_index].biases.data.len() {
-                file.write(&(self.layers[layer_index].biases.data[i][0].to_be_bytes()))
-                    .expect("Can't write biases to the save");
-            }
+    /// The network as an ONNX model, which fails if an activation has no ONNX equivalent
+    pub fn to_onnx(&self) -> Result<Vec<u8>, Error> {
+        model_onnx::encode(&self.layers, &self.metadata, &self.normalization)
+    }
+
+    /// Read back the ONNX model `bytes` and return its node count and the largest difference
+    /// between its outputs and the ones of the network on random pixels
+    pub fn check_onnx(&mut self, bytes: &[u8]) -> Result<(usize, f64), Error> {
+        let graph = model_onnx::decode(bytes)?;
+        let inputs = Matrix::random(4, self.input_size())
+            .map(&|x| (x + 1.0) / 2.0 * normalization::PIXEL_MAX);
+
+        let expected = self.feed_forwards_batch(inputs.clone());
+        let found = graph.evaluate(&inputs)?;
+        if (found.rows, found.cols) != (expected.rows, expected.cols) {
+            return Err(Error::InvalidOnnx(format!(
+                "the graph gives {}x{} outputs instead of {}x{}",
+                found.rows, found.cols, expected.rows, expected.cols
+            )));
         }
 
-        Ok(())
+        let difference = expected
+            .data
+            .iter()
+            .zip(&found.data)
+            .map(|(expected, found)| (expected - found).abs())
+            .fold(0.0, f64::max);
+        Ok((graph.node_count(), difference))
+    }
+
+    /// The architecture, weights, normalization and metadata as JSON, indented if `pretty`
+    pub fn to_json(&self, pretty: bool) -> Result<String, Error> {
+        model_json::encode(&self.layers, &self.metadata, &self.normalization, pretty)
     }
 }
+
+fn feed_forwards_layers(layers: &mut [Layer], inputs: Matrix) -> Matrix {
+    let mut current = inputs;
+    for layer in layers.iter_mut() {
+        current = layer.compute_output(current);
+    }
+    current
+}
+
+/// Accumulate the gradients of the batch in every layer and return the cost of the batch
+fn backpropagate(layers: &mut [Layer], inputs: &Matrix, targets: &Matrix, loss: &dyn Loss) -> f64 {
+    let layer_count = layers.len();
+    feed_forwards_layers(layers, inputs.clone());
+    let cost = layers[layer_count - 1].cost(targets, loss);
+
+    // update the gradient of the output layer
+    let mut node_values = layers[layer_count - 1].get_output_layer_node_value(targets, loss);
+    layers[layer_count - 1].update_gradients(&node_values);
+
+    // update the gradient of the hidden layers, from the last one to the first one
+    for i in (0..layer_count - 1).rev() {
+        node_values = layers[i].get_hidden_layer_node_value(&layers[i + 1].weights, &node_values);
+        layers[i].update_gradients(&node_values);
+    }
+
+    cost
+}
@@ -0,0 +1,111 @@
+use crate::{error::Error, matrix::matrix::Matrix};
+
+/// Largest value of the pixels, in the datasets as in the preprocessed images
+pub const PIXEL_MAX: f64 = 255.0;
+
+/// Transform applied to the inputs before the first layer, saved with the model so the inputs
+/// are transformed the same way while learning and while recognizing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Normalization {
+    /// The raw values
+    #[default]
+    None,
+    /// Pixels scaled from [0, 255] to [0, 1]
+    Unit,
+    /// Pixels scaled from [0, 255] to [-1, 1]
+    Symmetric,
+    /// Each input minus its mean, divided by its standard deviation (1 for the inputs constant
+    /// over the training samples)
+    Standardize { mean: Vec<f64>, std: Vec<f64> },
+}
+
+/// The normalization with that name, the standardization being computed from the inputs
+pub fn from_name(name: &str, inputs: &[Vec<f64>]) -> Option<Normalization> {
+    match name {
+        "none" => Some(Normalization::None),
+        "unit" => Some(Normalization::Unit),
+        "symmetric" => Some(Normalization::Symmetric),
+        "standardize" => Some(Normalization::standardize(inputs)),
+        _ => None,
+    }
+}
+
+impl Normalization {
+    /// Mean and standard deviation of every input over the samples
+    pub fn standardize(inputs: &[Vec<f64>]) -> Normalization {
+        let size = inputs.first().map_or(0, Vec::len);
+        let count = inputs.len().max(1) as f64;
+
+        let mut mean = vec![0.0; size];
+        for input in inputs {
+            for (mean, value) in mean.iter_mut().zip(input) {
+                *mean += value / count;
+            }
+        }
+
+        let mut variance = vec![0.0; size];
+        for input in inputs {
+            for ((variance, mean), value) in variance.iter_mut().zip(&mean).zip(input) {
+                *variance += (value - mean) * (value - mean) / count;
+            }
+        }
+        let std = variance
+            .into_iter()
+            .map(|variance: f64| {
+                if variance > 1e-12 {
+                    variance.sqrt()
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+
+        Normalization::Standardize { mean, std }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Normalization::None => "none",
+            Normalization::Unit => "unit",
+            Normalization::Symmetric => "symmetric",
+            Normalization::Standardize { .. } => "standardize",
+        }
+    }
+
+    /// Number of inputs the normalization is made for, `None` if it suits any
+    pub fn input_size(&self) -> Option<usize> {
+        match self {
+            Normalization::Standardize { mean, .. } => Some(mean.len()),
+            _ => None,
+        }
+    }
+
+    /// Whether the normalization suits a network taking `input_size` inputs
+    pub fn check_input_size(&self, input_size: usize) -> Result<(), Error> {
+        match self.input_size() {
+            Some(found) if found != input_size => Err(Error::NormalizationMismatch {
+                expected: input_size,
+                found,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Transform a batch of inputs, one sample per row
+    pub fn apply(&self, mut inputs: Matrix) -> Matrix {
+        match self {
+            Normalization::None => {}
+            Normalization::Unit => inputs.map_in_place(&|x| x / PIXEL_MAX),
+            Normalization::Symmetric => inputs.map_in_place(&|x| x / PIXEL_MAX * 2.0 - 1.0),
+            Normalization::Standardize { mean, std } => {
+                assert_eq!(inputs.cols, mean.len(), "Wrong input size");
+                for row in inputs.data.chunks_mut(mean.len()) {
+                    for ((value, mean), std) in row.iter_mut().zip(mean).zip(std) {
+                        *value = (*value - mean) / std;
+                    }
+                }
+            }
+        }
+        inputs
+    }
+}
@@ -0,0 +1,258 @@
+use crate::matrix::matrix::Matrix;
+
+/// Update rule of the parameters from their gradients.
+/// Every parameter matrix of the network is identified by an `id` (weights of the layer `i` are
+/// `2 * i`, its biases `2 * i + 1`) so the optimizer can keep a state for each of them.
+pub trait Optimizer: Send {
+    /// Move the parameter against its gradient (averaged over the batch)
+    fn update(&mut self, id: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64);
+
+    fn box_clone(&self) -> Box<dyn Optimizer>;
+}
+
+impl Clone for Box<dyn Optimizer> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Optimizer matching the name, with its usual hyperparameters
+pub fn from_name(name: &str) -> Option<Box<dyn Optimizer>> {
+    match name {
+        "sgd" => Some(Box::new(Sgd)),
+        "momentum" => Some(Box::new(Momentum::new(0.9))),
+        "nesterov" => Some(Box::new(Nesterov::new(0.9))),
+        "adagrad" => Some(Box::new(AdaGrad::new())),
+        "rmsprop" => Some(Box::new(RmsProp::new(0.9))),
+        "adam" => Some(Box::new(Adam::new(0.9, 0.999))),
+        "adamw" => Some(Box::new(AdamW::new(0.9, 0.999, 0.01))),
+        _ => None,
+    }
+}
+
+/// Added to the denominators to avoid divisions by zero
+const EPSILON: f64 = 1e-8;
+
+/// State of the parameter `id`, created with zeros the first time it is used
+fn state<'a>(states: &'a mut Vec<Matrix>, id: usize, parameter: &Matrix) -> &'a mut Matrix {
+    while states.len() <= id {
+        states.push(Matrix::zeros(0, 0));
+    }
+    if states[id].rows != parameter.rows || states[id].cols != parameter.cols {
+        states[id] = Matrix::zeros(parameter.rows, parameter.cols);
+    }
+    &mut states[id]
+}
+
+/// Plain stochastic gradient descent: p -= lr * g
+#[derive(Clone)]
+pub struct Sgd;
+
+impl Optimizer for Sgd {
+    fn update(&mut self, _: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64) {
+        parameter.add_scaled(-learning_rate, gradient);
+    }
+
+    fn box_clone(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// v = momentum * v + g, p -= lr * v
+#[derive(Clone)]
+pub struct Momentum {
+    momentum: f64,
+    velocities: Vec<Matrix>,
+}
+
+impl Momentum {
+    pub fn new(momentum: f64) -> Momentum {
+        Momentum {
+            momentum,
+            velocities: vec![],
+        }
+    }
+}
+
+impl Optimizer for Momentum {
+    fn update(&mut self, id: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64) {
+        let velocity = state(&mut self.velocities, id, parameter);
+        velocity.scale_in_place(self.momentum);
+        velocity.add_assign(gradient);
+
+        parameter.add_scaled(-learning_rate, velocity);
+    }
+
+    fn box_clone(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Momentum looking ahead: v = momentum * v + g, p -= lr * (g + momentum * v)
+#[derive(Clone)]
+pub struct Nesterov {
+    momentum: f64,
+    velocities: Vec<Matrix>,
+}
+
+impl Nesterov {
+    pub fn new(momentum: f64) -> Nesterov {
+        Nesterov {
+            momentum,
+            velocities: vec![],
+        }
+    }
+}
+
+impl Optimizer for Nesterov {
+    fn update(&mut self, id: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64) {
+        let velocity = state(&mut self.velocities, id, parameter);
+        velocity.scale_in_place(self.momentum);
+        velocity.add_assign(gradient);
+
+        parameter.add_scaled(-learning_rate, gradient);
+        parameter.add_scaled(-learning_rate * self.momentum, velocity);
+    }
+
+    fn box_clone(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Learning rate divided by the root of the sum of all the squared gradients
+#[derive(Clone, Default)]
+pub struct AdaGrad {
+    sums: Vec<Matrix>,
+}
+
+impl AdaGrad {
+    pub fn new() -> AdaGrad {
+        AdaGrad { sums: vec![] }
+    }
+}
+
+impl Optimizer for AdaGrad {
+    fn update(&mut self, id: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64) {
+        let sum = state(&mut self.sums, id, parameter);
+
+        for i in 0..parameter.data.len() {
+            let g = gradient.data[i];
+            sum.data[i] += g * g;
+            parameter.data[i] -= learning_rate * g / (sum.data[i].sqrt() + EPSILON);
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Learning rate divided by the root of a moving average of the squared gradients
+#[derive(Clone)]
+pub struct RmsProp {
+    decay: f64,
+    averages: Vec<Matrix>,
+}
+
+impl RmsProp {
+    pub fn new(decay: f64) -> RmsProp {
+        RmsProp {
+            decay,
+            averages: vec![],
+        }
+    }
+}
+
+impl Optimizer for RmsProp {
+    fn update(&mut self, id: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64) {
+        let average = state(&mut self.averages, id, parameter);
+
+        for i in 0..parameter.data.len() {
+            let g = gradient.data[i];
+            average.data[i] = self.decay * average.data[i] + (1.0 - self.decay) * g * g;
+            parameter.data[i] -= learning_rate * g / (average.data[i].sqrt() + EPSILON);
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Moving averages of the gradients and of the squared gradients, corrected for their zero start
+#[derive(Clone)]
+pub struct Adam {
+    beta1: f64,
+    beta2: f64,
+    first_moments: Vec<Matrix>,
+    second_moments: Vec<Matrix>,
+    /// Number of updates of each parameter
+    steps: Vec<i32>,
+}
+
+impl Adam {
+    pub fn new(beta1: f64, beta2: f64) -> Adam {
+        Adam {
+            beta1,
+            beta2,
+            first_moments: vec![],
+            second_moments: vec![],
+            steps: vec![],
+        }
+    }
+}
+
+impl Optimizer for Adam {
+    fn update(&mut self, id: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64) {
+        if self.steps.len() <= id {
+            self.steps.resize(id + 1, 0);
+        }
+        self.steps[id] += 1;
+
+        let first_correction = 1.0 - self.beta1.powi(self.steps[id]);
+        let second_correction = 1.0 - self.beta2.powi(self.steps[id]);
+        let first_moment = state(&mut self.first_moments, id, parameter);
+        let second_moment = state(&mut self.second_moments, id, parameter);
+
+        for i in 0..parameter.data.len() {
+            let g = gradient.data[i];
+            first_moment.data[i] = self.beta1 * first_moment.data[i] + (1.0 - self.beta1) * g;
+            second_moment.data[i] = self.beta2 * second_moment.data[i] + (1.0 - self.beta2) * g * g;
+
+            let m = first_moment.data[i] / first_correction;
+            let v = second_moment.data[i] / second_correction;
+            parameter.data[i] -= learning_rate * m / (v.sqrt() + EPSILON);
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Adam with the weight decay applied directly to the parameters instead of the gradients
+#[derive(Clone)]
+pub struct AdamW {
+    adam: Adam,
+    weight_decay: f64,
+}
+
+impl AdamW {
+    pub fn new(beta1: f64, beta2: f64, weight_decay: f64) -> AdamW {
+        AdamW {
+            adam: Adam::new(beta1, beta2),
+            weight_decay,
+        }
+    }
+}
+
+impl Optimizer for AdamW {
+    fn update(&mut self, id: usize, parameter: &mut Matrix, gradient: &Matrix, learning_rate: f64) {
+        parameter.scale_in_place(1.0 - learning_rate * self.weight_decay);
+        self.adam.update(id, parameter, gradient, learning_rate);
+    }
+
+    fn box_clone(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,46 @@
+#[derive(Clone, Debug)]
+pub struct Prediction {
+    /// Index of the most probable class (the digit for MNIST)
+    pub class: usize,
+    /// Softmax of the logits, sums to 1
+    pub probabilities: Vec<f64>,
+    /// Weighted outputs of the last layer, before its activation
+    pub logits: Vec<f64>,
+}
+
+impl Prediction {
+    pub fn from_logits(logits: Vec<f64>) -> Prediction {
+        // shift by the max so the exponentials can't overflow
+        let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let exponentials: Vec<f64> = logits.iter().map(|logit| (logit - max).exp()).collect();
+        let sum: f64 = exponentials.iter().sum();
+        let probabilities: Vec<f64> = exponentials.iter().map(|value| value / sum).collect();
+
+        let mut class = 0;
+        for i in 1..probabilities.len() {
+            if probabilities[i] > probabilities[class] {
+                class = i;
+            }
+        }
+
+        Prediction {
+            class,
+            probabilities,
+            logits,
+        }
+    }
+
+    /// Probability of the predicted class
+    pub fn confidence(&self) -> f64 {
+        self.probabilities[self.class]
+    }
+
+    /// The `k` most probable classes with their probability, most probable first
+    pub fn top_k(&self, k: usize) -> Vec<(usize, f64)> {
+        let mut classes: Vec<(usize, f64)> =
+            self.probabilities.iter().copied().enumerate().collect();
+        classes.sort_by(|a, b| b.1.total_cmp(&a.1));
+        classes.truncate(k);
+        classes
+    }
+}
@@ -0,0 +1,214 @@
+use std::f64::consts::PI;
+
+/// Learning rate to use at each batch of the training.
+/// `step` is the number of batches already learned, `base` the learning rate of the network.
+pub trait Schedule: Send {
+    fn learning_rate(&mut self, base: f64, step: usize) -> f64;
+
+    /// Validation metric (lower is better), measured every few batches while training (see
+    /// `Network::set_validation_interval`)
+    fn observe(&mut self, _metric: f64) {}
+
+    fn box_clone(&self) -> Box<dyn Schedule>;
+}
+
+impl Clone for Box<dyn Schedule> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Schedule matching the name, with hyperparameters suited to a training of `steps` batches
+pub fn from_name(name: &str, steps: usize) -> Option<Box<dyn Schedule>> {
+    match name {
+        "constant" => Some(Box::new(Constant)),
+        "step" => Some(Box::new(StepDecay::new(steps / 4, 0.5))),
+        "exponential" => Some(Box::new(Exponential::new(
+            0.01_f64.powf(1.0 / steps.max(1) as f64),
+        ))),
+        "cosine" => Some(Box::new(CosineAnnealing::new(steps / 4, 1, 0.0))),
+        "plateau" => Some(Box::new(ReduceOnPlateau::new(0.5, 2, 1e-4))),
+        _ => None,
+    }
+}
+
+/// Always the base learning rate
+#[derive(Clone)]
+pub struct Constant;
+
+impl Schedule for Constant {
+    fn learning_rate(&mut self, base: f64, _: usize) -> f64 {
+        base
+    }
+
+    fn box_clone(&self) -> Box<dyn Schedule> {
+        Box::new(self.clone())
+    }
+}
+
+/// Multiply the learning rate by `gamma` every `step_size` batches (at least 1)
+#[derive(Clone)]
+pub struct StepDecay {
+    step_size: usize,
+    gamma: f64,
+}
+
+impl StepDecay {
+    pub fn new(step_size: usize, gamma: f64) -> StepDecay {
+        StepDecay {
+            step_size: step_size.max(1),
+            gamma,
+        }
+    }
+}
+
+impl Schedule for StepDecay {
+    fn learning_rate(&mut self, base: f64, step: usize) -> f64 {
+        base * self.gamma.powi((step / self.step_size) as i32)
+    }
+
+    fn box_clone(&self) -> Box<dyn Schedule> {
+        Box::new(self.clone())
+    }
+}
+
+/// Multiply the learning rate by `gamma` after every batch
+#[derive(Clone)]
+pub struct Exponential {
+    gamma: f64,
+}
+
+impl Exponential {
+    pub fn new(gamma: f64) -> Exponential {
+        Exponential { gamma }
+    }
+}
+
+impl Schedule for Exponential {
+    fn learning_rate(&mut self, base: f64, step: usize) -> f64 {
+        base * self.gamma.powf(step as f64)
+    }
+
+    fn box_clone(&self) -> Box<dyn Schedule> {
+        Box::new(self.clone())
+    }
+}
+
+/// Go from the base learning rate down to `min_rate` along a half cosine, then restart.
+/// The first cycle lasts `period` batches, each one being `multiplier` times longer than the
+/// previous one (both at least 1).
+#[derive(Clone)]
+pub struct CosineAnnealing {
+    period: usize,
+    multiplier: usize,
+    min_rate: f64,
+}
+
+impl CosineAnnealing {
+    pub fn new(period: usize, multiplier: usize, min_rate: f64) -> CosineAnnealing {
+        CosineAnnealing {
+            period: period.max(1),
+            multiplier: multiplier.max(1),
+            min_rate,
+        }
+    }
+}
+
+impl Schedule for CosineAnnealing {
+    fn learning_rate(&mut self, base: f64, step: usize) -> f64 {
+        // find the cycle containing the step
+        let mut start = 0;
+        let mut period = self.period;
+        while step >= start + period {
+            start += period;
+            period *= self.multiplier;
+        }
+
+        let progress = (step - start) as f64 / period as f64;
+        self.min_rate + (base - self.min_rate) * (1.0 + (PI * progress).cos()) / 2.0
+    }
+
+    fn box_clone(&self) -> Box<dyn Schedule> {
+        Box::new(self.clone())
+    }
+}
+
+/// Grow the learning rate linearly during the first `steps` batches, then follow the schedule
+#[derive(Clone)]
+pub struct Warmup {
+    steps: usize,
+    schedule: Box<dyn Schedule>,
+}
+
+impl Warmup {
+    pub fn new(steps: usize, schedule: Box<dyn Schedule>) -> Warmup {
+        Warmup { steps, schedule }
+    }
+}
+
+impl Schedule for Warmup {
+    fn learning_rate(&mut self, base: f64, step: usize) -> f64 {
+        if step < self.steps {
+            base * (step + 1) as f64 / self.steps as f64
+        } else {
+            self.schedule.learning_rate(base, step - self.steps)
+        }
+    }
+
+    fn observe(&mut self, metric: f64) {
+        self.schedule.observe(metric);
+    }
+
+    fn box_clone(&self) -> Box<dyn Schedule> {
+        Box::new(self.clone())
+    }
+}
+
+/// Multiply the learning rate by `factor` when the validation metric hasn't improved for
+/// `patience` observations
+#[derive(Clone)]
+pub struct ReduceOnPlateau {
+    factor: f64,
+    patience: usize,
+    /// Smallest decrease of the metric counted as an improvement
+    threshold: f64,
+    best: f64,
+    waited: usize,
+    scale: f64,
+}
+
+impl ReduceOnPlateau {
+    pub fn new(factor: f64, patience: usize, threshold: f64) -> ReduceOnPlateau {
+        ReduceOnPlateau {
+            factor,
+            patience,
+            threshold,
+            best: f64::INFINITY,
+            waited: 0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Schedule for ReduceOnPlateau {
+    fn learning_rate(&mut self, base: f64, _: usize) -> f64 {
+        base * self.scale
+    }
+
+    fn observe(&mut self, metric: f64) {
+        if metric < self.best - self.threshold {
+            self.best = metric;
+            self.waited = 0;
+        } else {
+            self.waited += 1;
+            if self.waited > self.patience {
+                self.scale *= self.factor;
+                self.waited = 0;
+            }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Schedule> {
+        Box::new(self.clone())
+    }
+}
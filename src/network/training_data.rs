@@ -1,120 +1,224 @@
-use std::{fs::File, io::Read};
+use std::{
+    io::{BufRead, BufReader, Read},
+    iter::FusedIterator,
+};
 
+use flate2::bufread::MultiGzDecoder;
+
+use crate::{error::Error, idx};
+
+/// First bytes of the gzip files
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The labels are the digits
+const CLASS_COUNT: usize = 10;
+
+/// Samples of a dataset: the pixels of each image with the one-hot encoding of its label
 pub struct TrainingData {
     // images
     pub image_magic_number: u32,
     pub image_count: u32,
     pub rows_count: u32,
     pub cols_count: u32,
-    file_images: File,
+    images: idx::Reader<Box<dyn Read>>,
 
     // labels
     pub label_magic_number: u32,
     pub label_count: u32,
-    file_labels: File,
+    labels: Vec<usize>,
+
+    /// Number of samples already given
+    position: usize,
 }
 
 impl TrainingData {
-    pub fn new(file_images: &mut File, file_labels: &mut File) -> TrainingData {
-        let mut image_magic_number = [0; 4];
-        let mut label_magic_number = [0; 4];
-        let mut image_count = [0; 4];
-        let mut label_count = [0; 4];
-        let mut rows_count = [0; 4];
-        let mut cols_count = [0; 4];
-
-        file_images
-            .read(&mut image_magic_number)
-            .expect("Can't read the images magic number");
-        file_images
-            .read(&mut image_count)
-            .expect("Can't read the image count");
-        file_images
-            .read(&mut rows_count)
-            .expect("Can't read the rows count");
-        file_images
-            .read(&mut cols_count)
-            .expect("Can't read the cols count");
-
-        file_labels
-            .read(&mut label_magic_number)
-            .expect("Can't read the labels magic number");
-        file_labels
-            .read(&mut label_count)
-            .expect("Can't read the label count");
-
-        TrainingData {
-            image_magic_number: Self::bytes_to_u32(image_magic_number),
-            image_count: Self::bytes_to_u32(image_count),
-            rows_count: Self::bytes_to_u32(rows_count),
-            cols_count: Self::bytes_to_u32(cols_count),
-            file_images: file_images
-                .try_clone()
-                .expect("Can't clone the training data (->images) to store them"),
-            label_magic_number: Self::bytes_to_u32(label_magic_number),
-            label_count: Self::bytes_to_u32(label_count),
-            file_labels: file_labels
-                .try_clone()
-                .expect("Can't clone the training data (->labels) to store them"),
+    /// Read the headers of the IDX files and all the labels, the images having one item per
+    /// image (the other dimensions being the rows and the columns) and the labels a single
+    /// dimension. The files can be compressed with gzip, like the ones of the MNIST distribution.
+    pub fn new(
+        images: impl Read + 'static,
+        labels: impl Read + 'static,
+    ) -> Result<TrainingData, Error> {
+        let images = idx::Reader::new(decompressed(images)?)?;
+        let mut labels = idx::Reader::new(decompressed(labels)?)?;
+
+        let image_dims = &images.header().dims;
+        if image_dims.len() < 2 {
+            return Err(Error::InvalidIdx(
+                "the images file has a single dimension, the images need at least 2".to_string(),
+            ));
         }
+        if labels.header().item_size() != 1 {
+            return Err(Error::InvalidIdx(format!(
+                "the labels file has {} values per item instead of 1",
+                labels.header().item_size()
+            )));
+        }
+        let image_count = images.header().item_count();
+        let label_count = labels.header().item_count();
+        if image_count != label_count {
+            return Err(Error::InvalidIdx(format!(
+                "the images file has {} images but the labels file has {} labels",
+                image_count, label_count
+            )));
+        }
+
+        // the labels are small enough to be checked before reading any image
+        let label_values = (0..label_count)
+            .map(|i| {
+                let label = labels.read_item()?[0];
+                if label.fract() != 0.0 || !(0.0..CLASS_COUNT as f64).contains(&label) {
+                    return Err(Error::InvalidIdx(format!(
+                        "the label {} of the item {} isn't a digit",
+                        label,
+                        i + 1
+                    )));
+                }
+                Ok(label as usize)
+            })
+            .collect::<Result<Vec<usize>, Error>>()?;
+        labels.finish()?;
+
+        let dimension = |size: usize| {
+            u32::try_from(size)
+                .map_err(|_| Error::InvalidIdx("the dimensions are too large".to_string()))
+        };
+        Ok(TrainingData {
+            image_magic_number: images.header().magic_number(),
+            image_count: dimension(image_count)?,
+            rows_count: dimension(image_dims[1])?,
+            cols_count: dimension(image_dims[2..].iter().product())?,
+            images,
+            label_magic_number: labels.header().magic_number(),
+            label_count: dimension(label_count)?,
+            labels: label_values,
+            position: 0,
+        })
     }
 
-    pub fn bytes_to_u32(bytes: [u8; 4]) -> u32 {
-        ((bytes[3] as u32) << 0)
-            + ((bytes[2] as u32) << 8)
-            + ((bytes[1] as u32) << 16)
-            + ((bytes[0] as u32) << 24)
+    fn read_sample(&mut self, index: usize) -> Result<(Vec<f64>, Vec<f64>), Error> {
+        let inputs = self.images.read_item()?;
+        let mut outputs = vec![0.0; CLASS_COUNT];
+        outputs[self.labels[index]] = 1.0;
+
+        Ok((inputs, outputs))
+    }
+
+    /// Check that the images file ends after the last image, once the samples were read (the
+    /// ones not given yet are skipped)
+    pub fn finish(&mut self) -> Result<(), Error> {
+        while self.position < self.labels.len() {
+            self.images.read_item()?;
+            self.position += 1;
+        }
+        self.images.finish()
     }
 }
 
+/// The content of the file, decompressed if it starts with the gzip magic bytes
+fn decompressed(file: impl Read + 'static) -> Result<Box<dyn Read>, Error> {
+    let mut reader = BufReader::new(file);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Gives every sample once, or the error met while reading its image after which it ends, so the
+/// number of samples is only an upper bound. The end of the images file is checked by `finish`.
 impl Iterator for TrainingData {
-    type Item = (Vec<f64>, Vec<f64>);
+    type Item = Result<(Vec<f64>, Vec<f64>), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // read the label
-        let mut label_buffer = [0; 1];
-        let mut outputs: [f64; 10] = [0.0; 10];
-
-        self.file_labels
-            .read(&mut label_buffer)
-            .expect("Can't read the label");
-
-        outputs[label_buffer[0] as usize] = 1.0;
-
-        // read the image pixels
-        let mut inputs: Vec<f64> = Vec::<f64>::new();
-        for _ in 0..self.rows_count {
-            for _ in 0..self.cols_count {
-                let mut pixel = [0; 1];
-                self.file_images
-                    .read(&mut pixel)
-                    .expect("Can't read the pixel");
-                inputs.push(pixel[0] as f64)
-            }
+        if self.position >= self.labels.len() {
+            return None;
         }
 
-        let image: Self::Item = (inputs, outputs.to_vec());
-        Option::Some(image)
+        let sample = self.read_sample(self.position);
+        // the images following an error can't be located
+        self.position = if sample.is_ok() {
+            self.position + 1
+        } else {
+            self.labels.len()
+        };
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // an error ends the samples early
+        (0, Some(self.labels.len() - self.position))
     }
 }
 
-impl Clone for TrainingData {
-    fn clone(&self) -> Self {
-        Self {
-            image_magic_number: self.image_magic_number.clone(),
-            image_count: self.image_count.clone(),
-            rows_count: self.rows_count.clone(),
-            cols_count: self.cols_count.clone(),
-            file_images: self
-                .file_images
-                .try_clone()
-                .expect("Can't clone the training data (->images) to store them"),
-            label_magic_number: self.label_magic_number.clone(),
-            label_count: self.label_count.clone(),
-            file_labels: self
-                .file_labels
-                .try_clone()
-                .expect("Can't clone the training data (->labels) to store them"),
+impl FusedIterator for TrainingData {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    /// IDX file of bytes with the dimensions
+    fn idx(dims: &[u32], elements: &[u8]) -> Cursor<Vec<u8>> {
+        let mut bytes = vec![0, 0, 0x08, dims.len() as u8];
+        for dim in dims {
+            bytes.extend(dim.to_be_bytes());
+        }
+        bytes.extend(elements);
+        Cursor::new(bytes)
+    }
+
+    fn gzip(file: Cursor<Vec<u8>>) -> Cursor<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(file.get_ref()).unwrap();
+        Cursor::new(encoder.finish().unwrap())
+    }
+
+    fn samples(images: Cursor<Vec<u8>>, labels: Cursor<Vec<u8>>) -> Vec<(Vec<f64>, Vec<f64>)> {
+        let mut data = TrainingData::new(images, labels).unwrap();
+        let samples = data.by_ref().collect::<Result<_, Error>>().unwrap();
+        data.finish().unwrap();
+        samples
+    }
+
+    fn error(images: Cursor<Vec<u8>>, labels: Cursor<Vec<u8>>) -> String {
+        match TrainingData::new(images, labels) {
+            Err(Error::InvalidIdx(message)) => message,
+            Err(error) => panic!("expected an InvalidIdx error, got {}", error),
+            Ok(_) => panic!("expected an InvalidIdx error"),
         }
     }
+
+    #[test]
+    fn rejects_images_without_rows() {
+        assert_eq!(
+            error(idx(&[2], &[0, 0]), idx(&[2], &[1, 2])),
+            "the images file has a single dimension, the images need at least 2"
+        );
+    }
+
+    #[test]
+    fn rejects_labels_of_several_values() {
+        assert_eq!(
+            error(idx(&[2, 2, 2], &[0; 8]), idx(&[2, 2], &[1, 2, 3, 4])),
+            "the labels file has 2 values per item instead of 1"
+        );
+    }
+
+    #[test]
+    fn reads_gzip_files_like_plain_ones() {
+        let pixels: Vec<u8> = (0..3 * 2 * 2).map(|i| i * 20).collect();
+        let images = idx(&[3, 2, 2], &pixels);
+        let labels = idx(&[3], &[7, 0, 9]);
+
+        let plain = samples(images.clone(), labels.clone());
+        assert_eq!(plain.len(), 3);
+        assert_eq!(plain[2].0, vec![160.0, 180.0, 200.0, 220.0]);
+        assert_eq!(plain[0].1[7], 1.0);
+
+        assert_eq!(samples(gzip(images.clone()), gzip(labels.clone())), plain);
+        assert_eq!(samples(gzip(images), labels), plain);
+    }
 }
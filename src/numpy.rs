@@ -0,0 +1,517 @@
+//! NumPy `.npy` arrays and `.npz` archives, as written by `numpy.save`, `numpy.savez` and
+//! `numpy.savez_compressed`.
+//!
+//! A `.npy` file is the magic `\x93NUMPY`, the format version, the length of the header, the
+//! header (a Python dictionary literal giving the type of the elements, their order and the
+//! shape), then the elements. A `.npz` file is a zip archive of `.npy` files named after the
+//! arrays.
+
+use std::io::Read;
+
+use flate2::read::DeflateDecoder;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+/// The header and its length end on a multiple of this many bytes
+const HEADER_ALIGNMENT: usize = 64;
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Data {
+    /// Any numeric or boolean type, converted to `f64`
+    Numbers(Vec<f64>),
+    /// Unicode strings (`<U` type)
+    Strings(Vec<String>),
+}
+
+/// An array with its elements in C order (the last index changing the fastest)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Array {
+    pub shape: Vec<usize>,
+    pub data: Data,
+}
+
+impl Array {
+    pub fn numbers(shape: Vec<usize>, data: Vec<f64>) -> Array {
+        assert_eq!(shape.iter().product::<usize>(), data.len());
+        Array {
+            shape,
+            data: Data::Numbers(data),
+        }
+    }
+
+    /// A one dimensional array of strings
+    pub fn strings(values: Vec<String>) -> Array {
+        Array {
+            shape: vec![values.len()],
+            data: Data::Strings(values),
+        }
+    }
+
+    pub fn as_numbers(&self) -> Option<&[f64]> {
+        match &self.data {
+            Data::Numbers(numbers) => Some(numbers),
+            Data::Strings(_) => None,
+        }
+    }
+
+    pub fn as_strings(&self) -> Option<&[String]> {
+        match &self.data {
+            Data::Strings(strings) => Some(strings),
+            Data::Numbers(_) => None,
+        }
+    }
+}
+
+/// The array as a `.npy` file, the numbers being little-endian `f64`
+pub fn write_npy(array: &Array) -> Vec<u8> {
+    let (descr, element_size) = match &array.data {
+        Data::Numbers(_) => ("<f8".to_string(), 8),
+        Data::Strings(strings) => {
+            let length = strings.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+            (format!("<U{}", length.max(1)), 4 * length.max(1))
+        }
+    };
+
+    let shape = match array.shape.as_slice() {
+        [size] => format!("({},)", size),
+        sizes => format!(
+            "({})",
+            sizes
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+
+    // version 1 has a 2 bytes header length, version 2 a 4 bytes one
+    let prefix_length = if header.len() + HEADER_ALIGNMENT + 10 <= u16::MAX as usize {
+        10
+    } else {
+        12
+    };
+    let padding = (HEADER_ALIGNMENT - (prefix_length + header.len() + 1) % HEADER_ALIGNMENT)
+        % HEADER_ALIGNMENT;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(prefix_length + header.len() + element_size);
+    bytes.extend_from_slice(MAGIC);
+    if prefix_length == 10 {
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    } else {
+        bytes.extend_from_slice(&[2, 0]);
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    }
+    bytes.extend_from_slice(header.as_bytes());
+
+    match &array.data {
+        Data::Numbers(numbers) => {
+            for number in numbers {
+                bytes.extend_from_slice(&number.to_le_bytes());
+            }
+        }
+        Data::Strings(strings) => {
+            for string in strings {
+                let mut characters = string.chars();
+                for _ in 0..element_size / 4 {
+                    let code = characters.next().map_or(0, |c| c as u32);
+                    bytes.extend_from_slice(&code.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    bytes
+}
+
+/// Read a `.npy` file, in any byte order and element order
+pub fn read_npy(bytes: &[u8]) -> Result<Array, String> {
+    if !bytes.starts_with(MAGIC) {
+        return Err("not a .npy file".to_string());
+    }
+    let (header_start, header_length) = match bytes.get(6) {
+        Some(1) => (10, u16_at(bytes, 8)? as usize),
+        Some(2 | 3) => (12, u32_at(bytes, 8)? as usize),
+        Some(version) => return Err(format!("unsupported .npy version: {}", version)),
+        None => return Err("the data ends before the version".to_string()),
+    };
+    let header = bytes
+        .get(header_start..header_start + header_length)
+        .ok_or("the data ends before the end of the header")?;
+    let header = std::str::from_utf8(header).map_err(|_| "the header isn't valid UTF-8")?;
+
+    let descr = header_value(header, "descr")?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|descr| descr.strip_suffix('\''))
+        .ok_or_else(|| format!("unsupported element type: {}", descr))?;
+    let fortran_order = match header_value(header, "fortran_order")? {
+        "True" => true,
+        "False" => false,
+        value => return Err(format!("invalid fortran_order: {}", value)),
+    };
+    let shape = header_value(header, "shape")?;
+    let shape = shape
+        .strip_prefix('(')
+        .and_then(|shape| shape.strip_suffix(')'))
+        .ok_or_else(|| format!("invalid shape: {}", shape))?
+        .split(',')
+        .map(str::trim)
+        .filter(|size| !size.is_empty())
+        .map(|size| {
+            size.parse()
+                .map_err(|_| format!("invalid shape: {}", shape))
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+
+    let count = shape
+        .iter()
+        .try_fold(1usize, |count, &size| count.checked_mul(size))
+        .ok_or("the shape is too large")?;
+    let element_type = ElementType::parse(descr)?;
+    let content = &bytes[header_start + header_length..];
+    if content.len() / element_type.size < count {
+        return Err(format!(
+            "the data ends after {} of the {} elements",
+            content.len() / element_type.size,
+            count
+        ));
+    }
+
+    let elements = content.chunks_exact(element_type.size).take(count);
+    let mut data = match element_type.kind {
+        'U' => Data::Strings(
+            elements
+                .map(|element| element_type.string(element))
+                .collect(),
+        ),
+        _ => Data::Numbers(
+            elements
+                .map(|element| element_type.number(element))
+                .collect(),
+        ),
+    };
+    if fortran_order && shape.len() > 1 {
+        data = match data {
+            Data::Numbers(numbers) => Data::Numbers(to_c_order(&numbers, &shape)),
+            Data::Strings(strings) => Data::Strings(to_c_order(&strings, &shape)),
+        };
+    }
+
+    Ok(Array { shape, data })
+}
+
+/// The raw text of a value of the header dictionary
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, String> {
+    let start = header
+        .find(&format!("'{}':", key))
+        .or_else(|| header.find(&format!("\"{}\":", key)))
+        .ok_or_else(|| format!("the header has no {}", key))?
+        + key.len()
+        + 3;
+    let rest = header[start..].trim_start();
+
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|end| end + 1)
+    } else if rest.starts_with(['\'', '"']) {
+        rest[1..].find(['\'', '"']).map(|end| end + 2)
+    } else {
+        rest.find([',', '}'])
+    }
+    .ok_or_else(|| format!("invalid {} in the header", key))?;
+
+    Ok(rest[..end].trim())
+}
+
+/// Reorder the elements of an array stored with the first index changing the fastest
+fn to_c_order<T: Clone>(data: &[T], shape: &[usize]) -> Vec<T> {
+    let mut fortran_strides = vec![1; shape.len()];
+    for i in 1..shape.len() {
+        fortran_strides[i] = fortran_strides[i - 1] * shape[i - 1];
+    }
+
+    (0..data.len())
+        .map(|mut index| {
+            let mut offset = 0;
+            for (size, stride) in shape.iter().zip(&fortran_strides).rev() {
+                offset += index % size * stride;
+                index /= size;
+            }
+            data[offset].clone()
+        })
+        .collect()
+}
+
+struct ElementType {
+    little_endian: bool,
+    /// `f`, `i`, `u`, `b` or `U` like in the NumPy type strings
+    kind: char,
+    size: usize,
+}
+
+impl ElementType {
+    fn parse(descr: &str) -> Result<ElementType, String> {
+        let unsupported = || format!("unsupported element type: {}", descr);
+
+        let mut characters = descr.chars();
+        let little_endian = match characters.next() {
+            Some('<' | '|') => true,
+            Some('>') => false,
+            Some('=') => cfg!(target_endian = "little"),
+            _ => return Err(unsupported()),
+        };
+        let kind = characters.next().ok_or_else(unsupported)?;
+        let size: usize = characters.as_str().parse().map_err(|_| unsupported())?;
+
+        let size = match (kind, size) {
+            ('f', 4 | 8) | ('i' | 'u', 1 | 2 | 4 | 8) | ('b', 1) => size,
+            ('U', length) if length > 0 => 4 * length,
+            _ => return Err(unsupported()),
+        };
+
+        Ok(ElementType {
+            little_endian,
+            kind,
+            size,
+        })
+    }
+
+    fn number(&self, bytes: &[u8]) -> f64 {
+        // widened to 8 bytes in big-endian order, sign extended for the signed integers
+        let negative = self.kind == 'i'
+            && (if self.little_endian {
+                bytes[self.size - 1]
+            } else {
+                bytes[0]
+            }) & 0x80
+                != 0;
+        let mut wide = [if negative { 0xff } else { 0 }; 8];
+        for (i, &byte) in bytes.iter().enumerate() {
+            let position = if self.little_endian {
+                self.size - 1 - i
+            } else {
+                i
+            };
+            wide[8 - self.size + position] = byte;
+        }
+
+        match (self.kind, self.size) {
+            ('f', 4) => f32::from_be_bytes(wide[4..].try_into().unwrap()) as f64,
+            ('f', _) => f64::from_be_bytes(wide),
+            ('i', _) => i64::from_be_bytes(wide) as f64,
+            _ => u64::from_be_bytes(wide) as f64,
+        }
+    }
+
+    fn string(&self, bytes: &[u8]) -> String {
+        bytes
+            .chunks_exact(4)
+            .map(|code| {
+                let code = code.try_into().unwrap();
+                if self.little_endian {
+                    u32::from_le_bytes(code)
+                } else {
+                    u32::from_be_bytes(code)
+                }
+            })
+            .take_while(|&code| code != 0)
+            .map(|code| char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+/// The arrays as a `.npz` file, stored without compression like `numpy.savez` does
+pub fn write_npz(arrays: &[(String, Array)]) -> Vec<u8> {
+    // 1980-01-01 00:00, the earliest date of the zip format
+    const DATE: u16 = (1 << 5) | 1;
+
+    let mut bytes = Vec::new();
+    let mut central_directory = Vec::new();
+
+    for (name, array) in arrays {
+        let name = format!("{}.npy", name);
+        let content = write_npy(array);
+        let checksum = crc32fast::hash(&content);
+        let offset = bytes.len() as u32;
+
+        // fields shared by the local and the central headers, from the version needed
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&STORED.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&DATE.to_le_bytes());
+        fields.extend_from_slice(&checksum.to_le_bytes());
+        fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        bytes.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        bytes.extend_from_slice(&fields);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&content);
+
+        central_directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes());
+        central_directory.extend_from_slice(&fields);
+        // comment length, disk number, internal and external attributes
+        central_directory.extend_from_slice(&[0; 10]);
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = bytes.len() as u32;
+    bytes.extend_from_slice(&central_directory);
+    bytes.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(arrays.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&directory_offset.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+
+    bytes
+}
+
+/// Read every array of a `.npz` file, named without the `.npy` extension
+pub fn read_npz(bytes: &[u8]) -> Result<Vec<(String, Array)>, String> {
+    // the end of central directory record is followed by a comment of at most 65535 bytes
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(u16::MAX as usize + 1)
+        .find(|&offset| u32_at(bytes, offset) == Ok(END_OF_CENTRAL_DIRECTORY))
+        .ok_or("not a .npz file")?;
+
+    let mut entry_count = u16_at(bytes, end + 10)? as u64;
+    let mut directory_offset = u32_at(bytes, end + 16)? as u64;
+    if end >= 20 && u32_at(bytes, end - 20)? == ZIP64_LOCATOR {
+        let record = to_offset(u64_at(bytes, end - 12)?)?;
+        if u32_at(bytes, record)? != ZIP64_END_OF_CENTRAL_DIRECTORY {
+            return Err("invalid zip64 end of central directory".to_string());
+        }
+        entry_count = u64_at(bytes, record + 32)?;
+        directory_offset = u64_at(bytes, record + 48)?;
+    }
+
+    let mut arrays = Vec::new();
+    let mut offset = to_offset(directory_offset)?;
+    for _ in 0..entry_count {
+        if u32_at(bytes, offset)? != CENTRAL_HEADER {
+            return Err("invalid central directory".to_string());
+        }
+        let method = u16_at(bytes, offset + 10)?;
+        let checksum = u32_at(bytes, offset + 16)?;
+        let mut compressed_size = u32_at(bytes, offset + 20)? as u64;
+        let mut size = u32_at(bytes, offset + 24)? as u64;
+        let name_length = u16_at(bytes, offset + 28)? as usize;
+        let extra_length = u16_at(bytes, offset + 30)? as usize;
+        let comment_length = u16_at(bytes, offset + 32)? as usize;
+        let mut local_offset = u32_at(bytes, offset + 42)? as u64;
+        let name = slice(bytes, offset + 46, name_length)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+
+        // the values too large for 4 bytes are in the zip64 extra field, in this order
+        let extra = slice(bytes, offset + 46 + name_length, extra_length)?;
+        let mut extra_offset = 0;
+        while extra_offset + 4 <= extra.len() {
+            let id = u16_at(extra, extra_offset)?;
+            let length = u16_at(extra, extra_offset + 2)? as usize;
+            if id == ZIP64_EXTRA_FIELD {
+                let mut field = extra_offset + 4;
+                for value in [&mut size, &mut compressed_size, &mut local_offset] {
+                    if *value == u32::MAX as u64 {
+                        *value = u64_at(extra, field)?;
+                        field += 8;
+                    }
+                }
+            }
+            extra_offset += 4 + length;
+        }
+        offset += 46 + name_length + extra_length + comment_length;
+
+        let local_offset = to_offset(local_offset)?;
+        if u32_at(bytes, local_offset)? != LOCAL_HEADER {
+            return Err(format!("invalid local header of {}", name));
+        }
+        let data_offset = local_offset
+            + 30
+            + u16_at(bytes, local_offset + 26)? as usize
+            + u16_at(bytes, local_offset + 28)? as usize;
+        let compressed = slice(bytes, data_offset, to_offset(compressed_size)?)?;
+
+        let content = match method {
+            STORED => compressed.to_vec(),
+            DEFLATED => {
+                // a single byte past the declared size is enough to reject a larger content,
+                // without inflating all of it
+                let mut content = Vec::new();
+                DeflateDecoder::new(compressed)
+                    .take(size.saturating_add(1))
+                    .read_to_end(&mut content)
+                    .map_err(|error| format!("can't decompress {}: {}", name, error))?;
+                if content.len() as u64 > size {
+                    return Err(format!("{} is larger than its declared size", name));
+                }
+                content
+            }
+            _ => {
+                return Err(format!(
+                    "unsupported compression method of {}: {}",
+                    name, method
+                ))
+            }
+        };
+        if content.len() as u64 != size || crc32fast::hash(&content) != checksum {
+            return Err(format!("{} is corrupted", name));
+        }
+
+        let array = read_npy(&content).map_err(|error| format!("{}: {}", name, error))?;
+        let name = name.strip_suffix(".npy").unwrap_or(&name).to_string();
+        arrays.push((name, array));
+    }
+
+    Ok(arrays)
+}
+
+fn to_offset(value: u64) -> Result<usize, String> {
+    usize::try_from(value).map_err(|_| "the archive is too large".to_string())
+}
+
+fn slice(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8], String> {
+    bytes
+        .get(offset..offset.saturating_add(length))
+        .ok_or_else(|| "the data ends before the end of the archive".to_string())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    Ok(u16::from_le_bytes(
+        slice(bytes, offset, 2)?.try_into().unwrap(),
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(
+        slice(bytes, offset, 4)?.try_into().unwrap(),
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64, String> {
+    Ok(u64::from_le_bytes(
+        slice(bytes, offset, 8)?.try_into().unwrap(),
+    ))
+}
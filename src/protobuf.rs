@@ -0,0 +1,169 @@
+//! Protocol Buffers wire format, enough to write and read messages without their schema.
+//!
+//! A message is a sequence of fields, each one starting with a varint key holding the field
+//! number and the wire type of the value: varint, 64 bits, length-delimited (strings, bytes,
+//! sub-messages and packed repeated values) or 32 bits.
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+/// Builds a message field after field
+#[derive(Default)]
+pub struct Message {
+    bytes: Vec<u8>,
+}
+
+impl Message {
+    pub fn new() -> Message {
+        Message::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        write_varint(&mut self.bytes, ((field as u64) << 3) | wire_type as u64);
+    }
+
+    /// Any integer type but the `sint` ones, the negative values taking 10 bytes
+    pub fn int(&mut self, field: u32, value: i64) -> &mut Message {
+        self.key(field, VARINT);
+        write_varint(&mut self.bytes, value as u64);
+        self
+    }
+
+    pub fn float(&mut self, field: u32, value: f32) -> &mut Message {
+        self.key(field, FIXED32);
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Message {
+        self.key(field, LENGTH_DELIMITED);
+        write_varint(&mut self.bytes, value.len() as u64);
+        self.bytes.extend_from_slice(value);
+        self
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) -> &mut Message {
+        self.bytes(field, value.as_bytes())
+    }
+
+    pub fn message(&mut self, field: u32, message: &Message) -> &mut Message {
+        self.bytes(field, &message.bytes)
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Value<'a> {
+    Varint(u64),
+    /// `double`, `fixed64` and `sfixed64` values, unused by the ONNX models
+    #[allow(unused)]
+    Fixed64(u64),
+    LengthDelimited(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Value<'a> {
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Value::Varint(value) => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match *self {
+            Value::Fixed32(value) => Some(f32::from_bits(value)),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            Value::LengthDelimited(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<String> {
+        self.as_bytes()
+            .and_then(|bytes| String::from_utf8(bytes.to_vec()).ok())
+    }
+
+    /// The values of a repeated integer field, written one by one or packed
+    pub fn as_ints(&self) -> Option<Vec<i64>> {
+        match *self {
+            Value::Varint(value) => Some(vec![value as i64]),
+            Value::LengthDelimited(mut bytes) => {
+                let mut values = Vec::new();
+                while !bytes.is_empty() {
+                    values.push(read_varint(&mut bytes).ok()? as i64);
+                }
+                Some(values)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The fields of a message in their order of appearance, with their number
+pub fn parse(mut bytes: &[u8]) -> Result<Vec<(u32, Value<'_>)>, String> {
+    let mut fields = Vec::new();
+
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        let field = u32::try_from(key >> 3).map_err(|_| "invalid field number".to_string())?;
+        let value = match (key & 7) as u8 {
+            VARINT => Value::Varint(read_varint(&mut bytes)?),
+            FIXED64 => Value::Fixed64(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap())),
+            LENGTH_DELIMITED => {
+                let length = read_varint(&mut bytes)?;
+                let length = usize::try_from(length).map_err(|_| "invalid length".to_string())?;
+                Value::LengthDelimited(take(&mut bytes, length)?)
+            }
+            FIXED32 => Value::Fixed32(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap())),
+            wire_type => {
+                return Err(format!(
+                    "unsupported wire type {} of field {}",
+                    wire_type, field
+                ))
+            }
+        };
+        fields.push((field, value));
+    }
+
+    Ok(fields)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Result<&'a [u8], String> {
+    if count > bytes.len() {
+        return Err("the message ends before the end of a field".to_string());
+    }
+    let (taken, rest) = bytes.split_at(count);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = take(bytes, 1)?[0];
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err("invalid varint".to_string())
+}
@@ -0,0 +1,5 @@
+pub mod annotation;
+pub mod batch;
+pub mod preprocessing;
+pub mod recognition;
+pub mod segmentation;
@@ -0,0 +1,113 @@
+use image::{DynamicImage, Rgb, RgbImage};
+
+use super::{preprocessing::BoundingBox, recognition::NumberRecognition};
+
+/// 3x5 bitmap of the characters used in the labels, one row per `u8` (3 lowest bits)
+const FONT: [(char, [u8; 5]); 13] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+];
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Copy of the image with a box and a "digit confidence%" label around every recognized digit
+pub fn annotate(image: &DynamicImage, number: &NumberRecognition) -> RgbImage {
+    let mut annotated = image.to_rgb8();
+
+    // keep the boxes and labels readable on large images
+    let scale = (annotated.height().max(annotated.width()) / 200).max(1);
+
+    for digit in &number.digits {
+        let color = confidence_color(digit.confidence);
+        draw_rectangle(&mut annotated, &digit.bbox, scale, color);
+
+        let label = format!("{} {:.0}%", digit.digit, digit.confidence * 100.0);
+        let label_height = (GLYPH_HEIGHT + 2) * scale;
+
+        // above the box if there is enough room, under it otherwise
+        let y = if digit.bbox.y >= label_height + scale {
+            digit.bbox.y - label_height - scale
+        } else {
+            digit.bbox.y + digit.bbox.height + scale
+        };
+        draw_label(&mut annotated, &label, digit.bbox.x, y, scale, color);
+    }
+
+    annotated
+}
+
+/// Green when the network is confident, orange when it hesitates, red when it is likely wrong
+fn confidence_color(confidence: f64) -> Rgb<u8> {
+    if confidence >= 0.8 {
+        Rgb([0, 200, 0])
+    } else if confidence >= 0.5 {
+        Rgb([255, 140, 0])
+    } else {
+        Rgb([220, 0, 0])
+    }
+}
+
+fn fill_rectangle(image: &mut RgbImage, x: u32, y: u32, width: u32, height: u32, color: Rgb<u8>) {
+    for py in y..(y + height).min(image.height()) {
+        for px in x..(x + width).min(image.width()) {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// Outline the box with a border of `thickness` pixels drawn outside of it
+fn draw_rectangle(image: &mut RgbImage, bbox: &BoundingBox, thickness: u32, color: Rgb<u8>) {
+    let x = bbox.x.saturating_sub(thickness);
+    let y = bbox.y.saturating_sub(thickness);
+    let width = bbox.x + bbox.width + thickness - x;
+    let height = bbox.y + bbox.height + thickness - y;
+
+    fill_rectangle(image, x, y, width, thickness, color);
+    fill_rectangle(image, x, y + height - thickness, width, thickness, color);
+    fill_rectangle(image, x, y, thickness, height, color);
+    fill_rectangle(image, x + width - thickness, y, thickness, height, color);
+}
+
+/// Write the text in white over a rectangle of the given color
+fn draw_label(image: &mut RgbImage, text: &str, x: u32, y: u32, scale: u32, color: Rgb<u8>) {
+    let width = (text.len() as u32 * (GLYPH_WIDTH + 1) + 1) * scale;
+    let height = (GLYPH_HEIGHT + 2) * scale;
+    fill_rectangle(image, x, y, width, height, color);
+
+    for (index, character) in text.chars().enumerate() {
+        let glyph = match FONT.iter().find(|(c, _)| *c == character) {
+            Some((_, glyph)) => glyph,
+            None => continue,
+        };
+        let glyph_x = x + (index as u32 * (GLYPH_WIDTH + 1) + 1) * scale;
+
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits >> (GLYPH_WIDTH - 1 - col) & 1 == 1 {
+                    fill_rectangle(
+                        image,
+                        glyph_x + col * scale,
+                        y + (row as u32 + 1) * scale,
+                        scale,
+                        scale,
+                        WHITE,
+                    );
+                }
+            }
+        }
+    }
+}
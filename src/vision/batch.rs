@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use progress_bar::*;
+
+use crate::{json, network::network::Network};
+
+use super::preprocessing;
+
+/// Number of most probable digits kept in the report
+const TOP_COUNT: usize = 3;
+
+pub struct FileReport {
+    pub path: PathBuf,
+    /// Most probable digits with their probability, or why the file could not be classified
+    pub result: Result<Vec<(usize, f64)>, String>,
+}
+
+/// Classify every file of the directory and its sub directories
+pub fn classify_directory(
+    network: &mut Network,
+    directory: &Path,
+    rows: u32,
+    cols: u32,
+) -> io::Result<Vec<FileReport>> {
+    let mut paths = Vec::new();
+    list_files(directory, &mut paths)?;
+    paths.sort();
+
+    init_progress_bar(paths.len());
+    set_progress_bar_action("Classifying", Color::Blue, Style::Bold);
+
+    let mut reports = Vec::new();
+    for path in paths {
+        let result = match image::open(&path) {
+            Ok(image) => {
+                let input = preprocessing::preprocess(&image, rows, cols);
+                Ok(network.recognize(&input).top_k(TOP_COUNT))
+            }
+            Err(error) => Err(error.to_string()),
+        };
+        reports.push(FileReport { path, result });
+        inc_progress_bar();
+    }
+
+    set_progress_bar_action("Success", Color::Green, Style::Bold);
+    finalize_progress_bar();
+
+    Ok(reports)
+}
+
+fn list_files(directory: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// One line per file: path, prediction, the top digits with their probability and the error
+pub fn write_csv(reports: &[FileReport], writer: &mut dyn Write) -> io::Result<()> {
+    let mut header = String::from("path,prediction");
+    for i in 1..=TOP_COUNT {
+        header += &format!(",top{}_digit,top{}_probability", i, i);
+    }
+    writeln!(writer, "{},error", header)?;
+
+    for report in reports {
+        let path = csv_escape(&report.path.to_string_lossy());
+        match &report.result {
+            Ok(digits) => {
+                let mut line = format!("{},{}", path, digits[0].0);
+                for i in 0..TOP_COUNT {
+                    match digits.get(i) {
+                        Some((digit, probability)) => {
+                            line += &format!(",{},{}", digit, probability)
+                        }
+                        None => line += ",,",
+                    }
+                }
+                writeln!(writer, "{},", line)?;
+            }
+            Err(error) => writeln!(
+                writer,
+                "{},{}{}",
+                path,
+                ",".repeat(TOP_COUNT * 2 + 1),
+                csv_escape(error)
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+/// An array with one object per file, holding either the prediction or the error
+pub fn write_json(reports: &[FileReport], writer: &mut dyn Write) -> io::Result<()> {
+    writeln!(writer, "[")?;
+
+    for (index, report) in reports.iter().enumerate() {
+        let path = json::escape(&report.path.to_string_lossy());
+        let object = match &report.result {
+            Ok(digits) => {
+                let top: Vec<String> = digits
+                    .iter()
+                    .map(|(digit, probability)| {
+                        format!("{{\"digit\": {}, \"probability\": {}}}", digit, probability)
+                    })
+                    .collect();
+                format!(
+                    "{{\"path\": {}, \"prediction\": {}, \"top\": [{}]}}",
+                    path,
+                    digits[0].0,
+                    top.join(", ")
+                )
+            }
+            Err(error) => format!("{{\"path\": {}, \"error\": {}}}", path, json::escape(error)),
+        };
+        let separator = if index + 1 < reports.len() { "," } else { "" };
+        writeln!(writer, "  {}{}", object, separator)?;
+    }
+
+    writeln!(writer, "]")
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,188 @@
+use image::{imageops, DynamicImage, GrayImage, Luma};
+
+/// Size of the box the digit is fitted into before being centred (MNIST uses 20x20 in 28x28)
+pub const DIGIT_SIZE: u32 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Convert any image into the network input: a white digit centred on a black `rows x cols` frame
+pub fn preprocess(image: &DynamicImage, rows: u32, cols: u32) -> Vec<f64> {
+    frame_digit(&clean(image), rows, cols)
+}
+
+/// Grayscale version of the image with white ink over a pure black background
+pub fn clean(image: &DynamicImage) -> GrayImage {
+    let mut gray = image.to_luma8();
+    normalize_polarity(&mut gray);
+
+    let threshold = otsu_threshold(&gray);
+    remove_background(&mut gray, threshold);
+
+    gray
+}
+
+/// Fit the foreground of a white on black image into a `rows x cols` frame
+pub fn frame_digit(gray: &GrayImage, rows: u32, cols: u32) -> Vec<f64> {
+    let frame = match bounding_box(gray, 0) {
+        Some(bbox) => {
+            let digit =
+                imageops::crop_imm(gray, bbox.x, bbox.y, bbox.width, bbox.height).to_image();
+            center(&fit(&digit, DIGIT_SIZE.min(rows).min(cols)), rows, cols)
+        }
+        // nothing was written on the image
+        None => GrayImage::new(cols, rows),
+    };
+
+    frame.pixels().map(|p| p[0] as f64).collect()
+}
+
+/// Invert the image if the background is lighter than the digit
+pub fn normalize_polarity(gray: &mut GrayImage) {
+    let (width, height) = gray.dimensions();
+    let mut sum = 0.0;
+    let mut count = 0.0;
+
+    // the border of the image is considered to be the background
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+            sum += pixel[0] as f64;
+            count += 1.0;
+        }
+    }
+
+    if count > 0.0 && sum / count > 127.0 {
+        imageops::invert(gray);
+    }
+}
+
+/// Compute the threshold which best separates the two classes of the histogram (Otsu's method)
+pub fn otsu_threshold(gray: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+
+    let total = (gray.width() * gray.height()) as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum();
+
+    let mut sum_background = 0.0;
+    let mut weight_background = 0.0;
+    let mut best_variance = 0.0;
+    let mut threshold = 0;
+
+    for (value, count) in histogram.iter().enumerate() {
+        weight_background += *count as f64;
+        if weight_background == 0.0 {
+            continue;
+        }
+        let weight_foreground = total - weight_background;
+        if weight_foreground == 0.0 {
+            break;
+        }
+
+        sum_background += value as f64 * *count as f64;
+        let mean_background = sum_background / weight_background;
+        let mean_foreground = (sum - sum_background) / weight_foreground;
+
+        // between class variance
+        let variance =
+            weight_background * weight_foreground * (mean_background - mean_foreground).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            threshold = value as u8;
+        }
+    }
+
+    threshold
+}
+
+/// Set every pixel under the threshold to black
+pub fn remove_background(gray: &mut GrayImage, threshold: u8) {
+    for pixel in gray.pixels_mut() {
+        if pixel[0] <= threshold {
+            *pixel = Luma([0]);
+        }
+    }
+}
+
+/// Smallest box containing every pixel brighter than the threshold
+pub fn bounding_box(gray: &GrayImage, threshold: u8) -> Option<BoundingBox> {
+    let (mut min_x, mut min_y) = (u32::MAX, u32::MAX);
+    let (mut max_x, mut max_y) = (0, 0);
+
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        if pixel[0] > threshold {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+
+    if min_x == u32::MAX {
+        return None;
+    }
+
+    Some(BoundingBox {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x + 1,
+        height: max_y - min_y + 1,
+    })
+}
+
+/// Resize the image so its largest side is `size` pixels, keeping the aspect ratio
+pub fn fit(gray: &GrayImage, size: u32) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    let scale = size as f64 / width.max(height) as f64;
+    let new_width = ((width as f64 * scale).round() as u32).max(1);
+    let new_height = ((height as f64 * scale).round() as u32).max(1);
+
+    imageops::resize(gray, new_width, new_height, imageops::FilterType::Triangle)
+}
+
+/// Intensity weighted centre of the image
+pub fn center_of_mass(gray: &GrayImage) -> (f64, f64) {
+    let mut mass = 0.0;
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+
+    for (x, y, pixel) in gray.enumerate_pixels() {
+        let value = pixel[0] as f64;
+        mass += value;
+        sum_x += x as f64 * value;
+        sum_y += y as f64 * value;
+    }
+
+    if mass == 0.0 {
+        return (gray.width() as f64 / 2.0, gray.height() as f64 / 2.0);
+    }
+
+    (sum_x / mass, sum_y / mass)
+}
+
+/// Paste the digit in a `rows x cols` frame so its centre of mass is in the middle
+pub fn center(digit: &GrayImage, rows: u32, cols: u32) -> GrayImage {
+    let mut frame = GrayImage::new(cols, rows);
+    let (center_x, center_y) = center_of_mass(digit);
+
+    // keep the digit inside the frame even if its mass is off centre
+    let max_x = cols.saturating_sub(digit.width()) as i64;
+    let max_y = rows.saturating_sub(digit.height()) as i64;
+    let offset_x = ((cols as f64 / 2.0 - center_x).round() as i64).clamp(0, max_x);
+    let offset_y = ((rows as f64 / 2.0 - center_y).round() as i64).clamp(0, max_y);
+
+    imageops::overlay(&mut frame, digit, offset_x, offset_y);
+
+    frame
+}
@@ -0,0 +1,52 @@
+use image::DynamicImage;
+
+use crate::network::network::Network;
+
+use super::{preprocessing, preprocessing::BoundingBox, segmentation};
+
+#[derive(Clone, Debug)]
+pub struct DigitRecognition {
+    pub digit: usize,
+    pub confidence: f64,
+    pub bbox: BoundingBox,
+}
+
+#[derive(Clone, Debug)]
+pub struct NumberRecognition {
+    /// Recognized digits from left to right
+    pub digits: Vec<DigitRecognition>,
+}
+
+impl NumberRecognition {
+    /// The whole number as written on the image
+    pub fn value(&self) -> String {
+        self.digits
+            .iter()
+            .map(|digit| digit.digit.to_string())
+            .collect()
+    }
+}
+
+/// Segment the image and run every digit through the network
+pub fn recognize_number(
+    network: &mut Network,
+    image: &DynamicImage,
+    rows: u32,
+    cols: u32,
+) -> NumberRecognition {
+    let digits = segmentation::segment(image)
+        .into_iter()
+        .map(|segment| {
+            let input = preprocessing::frame_digit(&segment.image, rows, cols);
+            let prediction = network.recognize(&input);
+
+            DigitRecognition {
+                digit: prediction.class,
+                confidence: prediction.confidence(),
+                bbox: segment.bbox,
+            }
+        })
+        .collect();
+
+    NumberRecognition { digits }
+}
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+use image::{DynamicImage, GrayImage, Luma};
+
+use super::preprocessing::{self, BoundingBox};
+
+/// Components smaller than this fraction of the tallest one are considered as noise
+const MIN_HEIGHT_RATIO: f64 = 0.25;
+
+/// Usual width / height ratio of a handwritten digit
+const DIGIT_ASPECT: f64 = 0.7;
+
+/// Components wider than this ratio are considered as several touching digits
+const MAX_DIGIT_ASPECT: f64 = 1.1;
+
+#[derive(Clone)]
+pub struct Segment {
+    /// Position of the digit in the original image
+    pub bbox: BoundingBox,
+    /// White on black pixels of the digit, cropped to `bbox`
+    pub image: GrayImage,
+}
+
+/// Group of connected foreground pixels
+struct Component {
+    labels: Vec<u32>,
+    bbox: BoundingBox,
+}
+
+/// Find every digit of the image, sorted from left to right
+pub fn segment(image: &DynamicImage) -> Vec<Segment> {
+    let gray = preprocessing::clean(image);
+    let (labels, mut components) = connected_components(&gray);
+
+    // remove the noise
+    let max_height = components.iter().map(|c| c.bbox.height).max().unwrap_or(0);
+    components.retain(|c| c.bbox.height as f64 >= max_height as f64 * MIN_HEIGHT_RATIO);
+
+    // merge the broken strokes of a same digit
+    components.sort_by_key(|c| c.bbox.x);
+    let components = merge_overlapping(components);
+
+    let mut segments = Vec::new();
+    for component in components {
+        for (start, end) in split_touching(&gray, &labels, &component) {
+            if let Some(segment) = extract(&gray, &labels, &component, start, end) {
+                segments.push(segment);
+            }
+        }
+    }
+
+    segments
+}
+
+/// Label the 8-connected foreground regions of the image
+fn connected_components(gray: &GrayImage) -> (Vec<u32>, Vec<Component>) {
+    let (width, height) = gray.dimensions();
+    let mut labels = vec![0u32; (width * height) as usize];
+    let mut components = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for start_y in 0..height {
+        for start_x in 0..width {
+            let start = (start_y * width + start_x) as usize;
+            if gray.get_pixel(start_x, start_y)[0] == 0 || labels[start] != 0 {
+                continue;
+            }
+
+            let label = components.len() as u32 + 1;
+            let (mut min_x, mut min_y, mut max_x, mut max_y) = (start_x, start_y, start_x, start_y);
+            labels[start] = label;
+            queue.push_back((start_x, start_y));
+
+            // flood fill the component
+            while let Some((x, y)) = queue.pop_front() {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        let index = (ny * width + nx) as usize;
+                        if labels[index] == 0 && gray.get_pixel(nx, ny)[0] > 0 {
+                            labels[index] = label;
+                            queue.push_back((nx, ny));
+                        }
+                    }
+                }
+            }
+
+            components.push(Component {
+                labels: vec![label],
+                bbox: BoundingBox {
+                    x: min_x,
+                    y: min_y,
+                    width: max_x - min_x + 1,
+                    height: max_y - min_y + 1,
+                },
+            });
+        }
+    }
+
+    (labels, components)
+}
+
+/// Merge the components sharing most of their columns (components must be sorted by x)
+fn merge_overlapping(components: Vec<Component>) -> Vec<Component> {
+    let mut merged: Vec<Component> = Vec::new();
+
+    for component in components {
+        if let Some(last) = merged.last_mut() {
+            let overlap = (last.bbox.x + last.bbox.width)
+                .min(component.bbox.x + component.bbox.width) as i64
+                - component.bbox.x as i64;
+            let narrowest = last.bbox.width.min(component.bbox.width) as i64;
+
+            if overlap * 2 > narrowest {
+                let x = last.bbox.x.min(component.bbox.x);
+                let y = last.bbox.y.min(component.bbox.y);
+                let right =
+                    (last.bbox.x + last.bbox.width).max(component.bbox.x + component.bbox.width);
+                let bottom =
+                    (last.bbox.y + last.bbox.height).max(component.bbox.y + component.bbox.height);
+
+                last.labels.extend(component.labels);
+                last.bbox = BoundingBox {
+                    x,
+                    y,
+                    width: right - x,
+                    height: bottom - y,
+                };
+                continue;
+            }
+        }
+        merged.push(component);
+    }
+
+    merged
+}
+
+/// Column ranges of the digits contained in the component
+fn split_touching(gray: &GrayImage, labels: &[u32], component: &Component) -> Vec<(u32, u32)> {
+    let bbox = component.bbox;
+    let end = bbox.x + bbox.width;
+
+    if (bbox.width as f64) <= bbox.height as f64 * MAX_DIGIT_ASPECT {
+        return vec![(bbox.x, end)];
+    }
+
+    // number of foreground pixels of the component in each column
+    let projection: Vec<u32> = (bbox.x..end)
+        .map(|x| {
+            (bbox.y..bbox.y + bbox.height)
+                .filter(|y| {
+                    let label = labels[(y * gray.width() + x) as usize];
+                    component.labels.contains(&label)
+                })
+                .count() as u32
+        })
+        .collect();
+
+    let count = ((bbox.width as f64 / (bbox.height as f64 * DIGIT_ASPECT)).round() as u32).max(2);
+    let digit_width = bbox.width as f64 / count as f64;
+
+    // cut at the thinnest column around each expected boundary
+    let mut ranges = Vec::new();
+    let mut start = bbox.x;
+    for i in 1..count {
+        let expected = (digit_width * i as f64) as i64;
+        let window = (digit_width / 4.0) as i64;
+        let from = (expected - window).max(1) as usize;
+        let to = ((expected + window) as usize).min(projection.len() - 1);
+
+        let cut = (from..=to)
+            .min_by_key(|&x| projection[x])
+            .unwrap_or(expected as usize);
+        let cut = bbox.x + cut as u32;
+        if cut > start {
+            ranges.push((start, cut));
+            start = cut;
+        }
+    }
+    ranges.push((start, end));
+
+    ranges
+}
+
+/// Copy the pixels of the component between the `start` and `end` columns
+fn extract(
+    gray: &GrayImage,
+    labels: &[u32],
+    component: &Component,
+    start: u32,
+    end: u32,
+) -> Option<Segment> {
+    let bbox = component.bbox;
+    let mut image = GrayImage::new(end - start, bbox.height);
+
+    for y in 0..bbox.height {
+        for x in 0..end - start {
+            let (source_x, source_y) = (start + x, bbox.y + y);
+            let label = labels[(source_y * gray.width() + source_x) as usize];
+            if component.labels.contains(&label) {
+                image.put_pixel(x, y, Luma([gray.get_pixel(source_x, source_y)[0]]));
+            }
+        }
+    }
+
+    // shrink to the pixels actually belonging to this part
+    let tight = preprocessing::bounding_box(&image, 0)?;
+    let image =
+        image::imageops::crop_imm(&image, tight.x, tight.y, tight.width, tight.height).to_image();
+
+    Some(Segment {
+        bbox: BoundingBox {
+            x: start + tight.x,
+            y: bbox.y + tight.y,
+            width: tight.width,
+            height: tight.height,
+        },
+        image,
+    })
+}